@@ -0,0 +1,97 @@
+use crate::config::*;
+use crate::filesystem::*;
+
+use std::path::PathBuf;
+use std::fs;
+
+
+// Stored one per line as `<key> <path>`. The path may start with `~` and
+// contain environment variables, they are expanded when jumping. The added
+// paths are saved with every `$` doubled, so that they stay as they are.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum BookmarkAction {
+    GoTo,
+    Add,
+    Remove,
+}
+
+pub struct Bookmarks {
+    entries: Vec<(char, String)>,
+    file: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn load(file: Option<PathBuf>) -> (Bookmarks, Vec<String>) {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        if let Some(file) = file.as_ref() {
+            match fs::read_to_string(file) {
+                Ok(contents) => for (index, line) in contents.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') { continue; }
+                    let mut chars = line.chars();
+                    let key = chars.next().unwrap(); // the line is not empty
+                    let path = chars.as_str().trim();
+                    if path.is_empty() {
+                        errors.push(format!("bookmarks:{}: expected `<key> <path>`", index + 1));
+                    } else {
+                        entries.retain(|(existing, _)| *existing != key);
+                        entries.push((key, path.to_string()));
+                    }
+                },
+                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                Err(e) => errors.push(format!("Could not read {}: {}", file.display(), e)),
+            }
+        }
+        (Bookmarks { entries, file }, errors)
+    }
+
+    pub fn entries(&self) -> &Vec<(char, String)> {
+        &self.entries
+    }
+
+    pub fn get(&self, key: char) -> Option<PathBuf> {
+        self.entries.iter().find(|(existing, _)| *existing == key).map(|(_, path)| expand_path(path))
+    }
+
+    pub fn add(&mut self, key: char, path: &PathBuf) -> Result<(), String> {
+        let path = escape_for_expansion(&path_to_string(path));
+        if let Some(entry) = self.entries.iter_mut().find(|(existing, _)| *existing == key) {
+            entry.1 = path;
+        } else {
+            self.entries.push((key, path));
+        }
+        self.save()
+    }
+
+    // Returns whether there was such a bookmark
+    pub fn remove(&mut self, key: char) -> Result<bool, String> {
+        let amount_before = self.entries.len();
+        self.entries.retain(|(existing, _)| *existing != key);
+        if self.entries.len() == amount_before { return Ok(false); }
+        self.save().map(|_| true)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let file = self.file.as_ref().ok_or("Nowhere to save the bookmarks")?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let contents: String = self.entries.iter()
+            .map(|(key, path)| format!("{} {}\n", key, path))
+            .collect();
+        fs::write(file, contents).map_err(|e| format!("Could not save bookmarks: {}", e))
+    }
+}
+
+pub fn default_bookmarks_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("bookmarks"))
+}
+
+pub fn prompt_of(action: BookmarkAction) -> &'static str {
+    match action {
+        BookmarkAction::GoTo   => "Go to bookmark:",
+        BookmarkAction::Add    => "Add bookmark under key:",
+        BookmarkAction::Remove => "Remove bookmark:",
+    }
+}
@@ -0,0 +1,272 @@
+use crate::filesystem::*;
+
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+
+
+// The entries are written one per line: by name if they are inside the current
+// directory, otherwise by absolute path. The edited file must keep the lines in
+// the same order; a line may not be removed nor emptied.
+//-----------------------------------------------------------------------------
+pub type Rename = (PathBuf, PathBuf); // from, to
+
+pub fn line_of(path: &PathBuf, parent_path: &Path) -> String {
+    if path.parent() == Some(parent_path) { file_name(path) }
+    else                                            { path_to_string(path) }
+}
+
+// Returns the temporary file with the names, which the caller has to remove
+pub fn write_names(paths: &[PathBuf], parent_path: &Path) -> Result<PathBuf, String> {
+    let mut contents = String::new();
+    for path in paths.iter() {
+        let line = line_of(path, parent_path);
+        if line.contains('\n') {
+            return Err(format!("Can not bulk rename {:?}, it contains a newline", line));
+        }
+        contents += &line;
+        contents += "\n";
+    }
+    let (path, mut file) = create_temp_file()?;
+    if let Err(e) = file.write_all(contents.as_bytes()) {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Could not write {}: {}", path.display(), e));
+    }
+    Ok(path)
+}
+
+// Only a new file with an unpredictable name, readable by nobody else, so that
+// another user can neither read the names nor plant a symlink in its place
+fn create_temp_file() -> Result<(PathBuf, File), String> {
+    for _ in 0..100 {
+        let suffix = RandomState::new().build_hasher().finish();
+        let path = std::env::temp_dir().join(format!("vffm-bulk-rename-{}-{:016x}", std::process::id(), suffix));
+        match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Could not create {}: {}", path.display(), e)),
+        }
+    }
+    Err("Could not create a temporary file".to_string())
+}
+
+// Checks the edited names and returns the renames that they mean
+pub fn plan_renames(paths: &[PathBuf], parent_path: &Path, edited: &str)
+        -> Result<Vec<Rename>, String> {
+    let lines: Vec<&str> = edited.lines().collect();
+    if lines.len() != paths.len() {
+        return Err(format!("Expected {} lines but got {}: lines may not be added or removed",
+                           paths.len(), lines.len()));
+    }
+
+    let mut renames = Vec::new();
+    for (path, line) in paths.iter().zip(lines) {
+        if line == line_of(path, parent_path) { continue; } // left as it was
+        if line.trim().is_empty() {
+            return Err(format!("{} has lost its name: deleting is not done by renaming",
+                               line_of(path, parent_path)));
+        }
+        let new_path = if path.parent() == Some(parent_path) {
+            if line.contains('/') || line == "." || line == ".." {
+                return Err(format!("`{}` is not a valid name", line));
+            }
+            parent_path.join(line)
+        } else {
+            let new_path = PathBuf::from(line);
+            if !new_path.is_absolute() || new_path.file_name().is_none() {
+                return Err(format!("`{}` is not an absolute path", line));
+            }
+            new_path
+        };
+        renames.push((path.clone(), new_path));
+    }
+
+    check_renames(&renames)?;
+    Ok(renames)
+}
+
+pub fn check_renames(renames: &[Rename]) -> Result<(), String> {
+    let sources: HashSet<&PathBuf> = renames.iter().map(|(from, _)| from).collect();
+    let mut targets: HashSet<&PathBuf> = HashSet::new();
+    for (from, to) in renames.iter() {
+        if !targets.insert(to) {
+            return Err(format!("More than one entry would be renamed to {}", to.display()));
+        }
+        // Taking the place of an entry that moves away is fine, otherwise it would be lost
+        if !sources.contains(to) && fs::symlink_metadata(to).is_ok() {
+            return Err(format!("Can not rename {}: {} already exists", file_name(from), to.display()));
+        }
+    }
+    Ok(())
+}
+
+// Tells for every rename whether it collides with another one or with an existing entry
+pub fn collisions(renames: &[Rename]) -> Vec<bool> {
+    let sources: HashSet<&PathBuf> = renames.iter().map(|(from, _)| from).collect();
+    renames.iter().map(|(_, to)| {
+        renames.iter().filter(|(_, other)| other == to).count() > 1
+            || (!sources.contains(to) && fs::symlink_metadata(to).is_ok())
+    }).collect()
+}
+
+// Orders the renames so that no entry is overwritten. Cycles such as a->b, b->a
+// are broken by first moving one of the entries to a temporary name.
+pub fn order_renames(mut pending: Vec<Rename>) -> Vec<Rename> {
+    let mut steps = Vec::new();
+    let mut number = 0;
+    while !pending.is_empty() {
+        let free = pending.iter().position(|(_, to)| !pending.iter().any(|(from, _)| from == to));
+        match free {
+            Some(index) => steps.push(pending.remove(index)),
+            None => { // everything left is in cycles
+                let temporary = temporary_path_near(&pending[0].0, &mut number);
+                steps.push((pending[0].0.clone(), temporary.clone()));
+                pending[0].0 = temporary;
+            },
+        }
+    }
+    steps
+}
+
+// The number keeps growing, so that every cycle gets its own name
+fn temporary_path_near(path: &Path, number: &mut usize) -> PathBuf {
+    let parent = path.parent().unwrap(); // the root is never renamed
+    loop {
+        let candidate = parent.join(format!(".vffm-rename-{}-{}", std::process::id(), number));
+        *number += 1;
+        if fs::symlink_metadata(&candidate).is_err() { return candidate; }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    const PARENT: &str = "/surely/not/there";
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(|name| PathBuf::from(PARENT).join(name)).collect()
+    }
+
+    fn rename(from: &str, to: &str) -> Rename {
+        (PathBuf::from(PARENT).join(from), PathBuf::from(PARENT).join(to))
+    }
+
+    // Plays the steps on a directory of entries named after their contents,
+    // no step may take the place of an entry that is still there
+    fn apply(steps: &[Rename], names: &[&str]) -> HashMap<PathBuf, String> {
+        let mut entries: HashMap<PathBuf, String> =
+            paths(names).into_iter().zip(names.iter().map(|name| name.to_string())).collect();
+        for (from, to) in steps.iter() {
+            assert!(!entries.contains_key(to), "{} would be overwritten", to.display());
+            let contents = entries.remove(from).unwrap();
+            entries.insert(to.clone(), contents);
+        }
+        entries
+    }
+
+    #[test]
+    fn line_of_is_the_name_only_inside_the_directory() {
+        let parent = PathBuf::from(PARENT);
+        assert_eq!(line_of(&parent.join("a b"), &parent), "a b");
+        assert_eq!(line_of(&PathBuf::from("/elsewhere/c"), &parent), "/elsewhere/c");
+    }
+
+    #[test]
+    fn write_names_makes_a_private_file() {
+        let file = write_names(&paths(&["a", "b"]), &PathBuf::from(PARENT)).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "a\nb\n");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_file(&file).unwrap();
+        assert!(write_names(&paths(&["a\nb"]), &PathBuf::from(PARENT)).unwrap_err().contains("newline"));
+    }
+
+    #[test]
+    fn plan_renames_skips_the_unchanged_lines() {
+        let parent = PathBuf::from(PARENT);
+        let renames = plan_renames(&paths(&["a", "b", "c"]), &parent, "a\nB\nc\n").unwrap();
+        assert_eq!(renames, vec![rename("b", "B")]);
+        assert_eq!(plan_renames(&paths(&["a"]), &parent, "a\n").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn plan_renames_takes_absolute_paths_for_entries_elsewhere() {
+        let parent = PathBuf::from(PARENT);
+        let elsewhere = vec![PathBuf::from("/elsewhere/c")];
+        assert_eq!(plan_renames(&elsewhere, &parent, "/elsewhere/d").unwrap(),
+                   vec![(PathBuf::from("/elsewhere/c"), PathBuf::from("/elsewhere/d"))]);
+        assert_eq!(plan_renames(&elsewhere, &parent, "d").unwrap_err(), "`d` is not an absolute path");
+        assert_eq!(plan_renames(&elsewhere, &parent, "/").unwrap_err(), "`/` is not an absolute path");
+    }
+
+    #[test]
+    fn plan_renames_rejects_bad_edits() {
+        let parent = PathBuf::from(PARENT);
+        let paths = paths(&["a", "b"]);
+        assert_eq!(plan_renames(&paths, &parent, "a\n").unwrap_err(),
+                   "Expected 2 lines but got 1: lines may not be added or removed");
+        assert_eq!(plan_renames(&paths, &parent, "a\nb\nc\n").unwrap_err(),
+                   "Expected 2 lines but got 3: lines may not be added or removed");
+        assert_eq!(plan_renames(&paths, &parent, "a\n  \n").unwrap_err(),
+                   "b has lost its name: deleting is not done by renaming");
+        assert_eq!(plan_renames(&paths, &parent, "a\nx/y\n").unwrap_err(), "`x/y` is not a valid name");
+        assert_eq!(plan_renames(&paths, &parent, "a\n..\n").unwrap_err(), "`..` is not a valid name");
+        assert_eq!(plan_renames(&paths, &parent, "c\nc\n").unwrap_err(),
+                   format!("More than one entry would be renamed to {}/c", PARENT));
+    }
+
+    #[test]
+    fn check_renames_lets_entries_swap_but_not_overwrite() {
+        assert!(check_renames(&[rename("a", "b"), rename("b", "a")]).is_ok());
+
+        let dir = std::env::temp_dir().join(format!("vffm-bulk-rename-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("taken"), "").unwrap();
+        let renames = vec![(dir.join("a"), dir.join("taken"))];
+        assert_eq!(check_renames(&renames).unwrap_err(),
+                   format!("Can not rename a: {} already exists", dir.join("taken").display()));
+        assert_eq!(collisions(&renames), vec![true]);
+        // Unless the existing one moves away too
+        let renames = vec![(dir.join("a"), dir.join("taken")), (dir.join("taken"), dir.join("b"))];
+        assert!(check_renames(&renames).is_ok());
+        assert_eq!(collisions(&renames), vec![false, false]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn order_renames_moves_out_of_the_way_first() {
+        let steps = order_renames(vec![rename("a", "b"), rename("b", "c")]);
+        assert_eq!(steps, vec![rename("b", "c"), rename("a", "b")]);
+        let entries = apply(&steps, &["a", "b"]);
+        assert_eq!(entries[&PathBuf::from(PARENT).join("b")], "a");
+        assert_eq!(entries[&PathBuf::from(PARENT).join("c")], "b");
+    }
+
+    #[test]
+    fn order_renames_breaks_cycles() {
+        let steps = order_renames(vec![rename("a", "b"), rename("b", "a")]);
+        assert_eq!(steps.len(), 3);
+        let entries = apply(&steps, &["a", "b"]);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[&PathBuf::from(PARENT).join("a")], "b");
+        assert_eq!(entries[&PathBuf::from(PARENT).join("b")], "a");
+    }
+
+    #[test]
+    fn order_renames_gives_every_cycle_its_own_temporary_name() {
+        let renames = vec![rename("a", "b"), rename("b", "a"), rename("x", "y"), rename("y", "z"), rename("z", "x")];
+        let steps = order_renames(renames);
+        assert_eq!(steps.len(), 7);
+        let entries = apply(&steps, &["a", "b", "x", "y", "z"]);
+        assert_eq!(entries.len(), 5);
+        for (from, to) in [("a", "b"), ("b", "a"), ("x", "y"), ("y", "z"), ("z", "x")].iter() {
+            assert_eq!(entries[&PathBuf::from(PARENT).join(to)], *from);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::input::*;
+use crate::config::*;
+
+use std::path::PathBuf;
+use std::fs;
+
+
+pub struct Arguments {
+    pub start_dir: PathBuf,
+    pub start_entry: Option<String>, // the name to put the cursor on
+    pub show_hidden: Option<bool>,
+    pub sorting_type: Option<SortingType>,
+    pub config_path: Option<PathBuf>,
+    pub shell_outputs: ShellOutputs,
+}
+
+// Where to report the outcome of the session for a shell wrapper to pick up
+#[derive(Clone)]
+pub struct ShellOutputs {
+    pub choosedir: Option<PathBuf>,
+    pub selectfile: Option<PathBuf>,
+}
+
+// What the session ended with: the directory of the last tab and
+// the selected entries (or the entry under cursor if nothing was selected)
+pub struct ExitState {
+    pub dir: PathBuf,
+    pub chosen: Vec<PathBuf>,
+}
+
+pub enum ParsedArguments {
+    Run(Arguments),
+    Exit(String), // the text to print, e.g. for --help
+}
+
+fn usage() -> String {
+    format!("\
+Usage: vffm [OPTIONS] [PATH]
+
+Opens PATH (the current directory by default). If PATH is a file, opens its
+directory with the cursor on it.
+
+Options:
+  -a, --show-hidden        Show hidden files
+  -A, --hide-hidden        Do not show hidden files
+  -s, --sort <TYPE>        Initial sorting: lexicographically, case_insensitive, natural,
+                           extension, size, time_modified, time_changed, time_accessed or any
+  -c, --config <FILE>      Read the settings from FILE instead of {}
+      --choosedir <FILE>   On exit write the last visited directory into FILE
+      --selectfile <FILE>  On exit write the selected paths (or the one under
+                           cursor) into FILE, one per line
+  -V, --version            Print version and exit
+  -h, --help               Print this help and exit",
+        default_config_path().map(|path| path.display().to_string())
+                             .unwrap_or("$XDG_CONFIG_HOME/vffm/config".to_string()))
+}
+
+pub fn parse_arguments<I: Iterator<Item = String>>(mut args: I) -> Result<ParsedArguments, String> {
+    let mut start_path: Option<PathBuf> = None;
+    let mut show_hidden = None;
+    let mut sorting_type = None;
+    let mut config_path = None;
+    let mut choosedir = None;
+    let mut selectfile = None;
+    while let Some(arg) = args.next() {
+        let mut value_for = |option: &str| args.next().ok_or(format!("`{}` needs a value", option));
+        match arg.as_str() {
+            "-h" | "--help"        => return Ok(ParsedArguments::Exit(usage())),
+            "-V" | "--version"     => return Ok(ParsedArguments::Exit(
+                                          format!("vffm {}", env!("CARGO_PKG_VERSION")))),
+            "-a" | "--show-hidden" => show_hidden = Some(true),
+            "-A" | "--hide-hidden" => show_hidden = Some(false),
+            "-s" | "--sort"        => sorting_type = Some(parse_sorting_type(&value_for(&arg)?)?),
+            "-c" | "--config"      => config_path = Some(PathBuf::from(value_for(&arg)?)),
+            "--choosedir"          => choosedir   = Some(PathBuf::from(value_for(&arg)?)),
+            "--selectfile"         => selectfile  = Some(PathBuf::from(value_for(&arg)?)),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option `{}`", arg)),
+            _ => {
+                if start_path.is_some() { return Err("only one PATH may be given".to_string()); }
+                start_path = Some(PathBuf::from(arg));
+            },
+        }
+    }
+
+    let (start_dir, start_entry) = resolve_start_path(start_path)?;
+    Ok(ParsedArguments::Run(Arguments {
+        start_dir,
+        start_entry,
+        show_hidden,
+        sorting_type,
+        config_path,
+        shell_outputs: ShellOutputs { choosedir, selectfile },
+    }))
+}
+
+fn resolve_start_path(path: Option<PathBuf>) -> Result<(PathBuf, Option<String>), String> {
+    let current_dir = std::env::current_dir()
+        .map_err(|e| format!("could not get the current directory: {}", e))?;
+    let path = match path {
+        Some(path) => current_dir.join(path),
+        None       => current_dir,
+    };
+    if path.is_dir() {
+        let path = path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?;
+        return Ok((path, None));
+    }
+    // A symlink to a file is shown where it is, not where it points to
+    fs::symlink_metadata(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string());
+    let parent = path.parent().unwrap(); // a file is never the root
+    let parent = parent.canonicalize().map_err(|e| format!("{}: {}", parent.display(), e))?;
+    Ok((parent, name))
+}
+
+pub fn write_shell_outputs(outputs: &ShellOutputs, state: &ExitState) -> Result<(), String> {
+    if let Some(file) = outputs.choosedir.as_ref() {
+        fs::write(file, format!("{}\n", state.dir.display()))
+            .map_err(|e| format!("could not write {}: {}", file.display(), e))?;
+    }
+    if let Some(file) = outputs.selectfile.as_ref() {
+        let contents: String = state.chosen.iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect();
+        fs::write(file, contents)
+            .map_err(|e| format!("could not write {}: {}", file.display(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for one test, the tests run in parallel
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vffm-cli-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn parse(args: &[&str]) -> Result<ParsedArguments, String> {
+        parse_arguments(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run_arguments(args: &[&str]) -> Arguments {
+        match parse(args) {
+            Ok(ParsedArguments::Run(arguments)) => arguments,
+            _ => panic!("{:?} should start a session", args),
+        }
+    }
+
+    fn error_of(args: &[&str]) -> String {
+        match parse(args) {
+            Err(error) => error,
+            Ok(_)      => panic!("{:?} should not parse", args),
+        }
+    }
+
+    #[test]
+    fn options_are_read() {
+        let dir = test_dir("options");
+        let dir_arg = dir.to_str().unwrap();
+        let arguments = run_arguments(&["-a", "--sort", "size", "-c", "/x/config", dir_arg]);
+        assert_eq!(arguments.show_hidden, Some(true));
+        assert_eq!(arguments.sorting_type, Some(SortingType::Size));
+        assert_eq!(arguments.config_path, Some(PathBuf::from("/x/config")));
+        assert_eq!(arguments.start_dir, dir);
+        assert_eq!(arguments.start_entry, None);
+        assert_eq!(run_arguments(&["-A", dir_arg]).show_hidden, Some(false));
+        assert_eq!(run_arguments(&["-a", "-A", dir_arg]).show_hidden, Some(false)); // the last one wins
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn help_and_version_exit() {
+        match parse(&["--help", "--nonsense"]) {
+            Ok(ParsedArguments::Exit(text)) => assert!(text.starts_with("Usage: vffm")),
+            _ => panic!("--help should exit"),
+        }
+        match parse(&["-V"]) {
+            Ok(ParsedArguments::Exit(text)) => assert!(text.starts_with("vffm ")),
+            _ => panic!("-V should exit"),
+        }
+    }
+
+    #[test]
+    fn bad_arguments_are_reported() {
+        assert_eq!(error_of(&["--nonsense"]), "unknown option `--nonsense`");
+        assert_eq!(error_of(&["--sort"]), "`--sort` needs a value");
+        assert_eq!(error_of(&["/", "/"]), "only one PATH may be given");
+        assert!(error_of(&["/surely/not/there"]).starts_with("/surely/not/there: "));
+    }
+
+    #[test]
+    fn a_file_opens_its_directory_on_it() {
+        let dir = test_dir("file");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let arguments = run_arguments(&[dir.join("notes.txt").to_str().unwrap()]);
+        assert_eq!(arguments.start_dir, dir);
+        assert_eq!(arguments.start_entry, Some("notes.txt".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_symlink_to_a_file_opens_where_the_link_is() {
+        let dir = test_dir("symlink");
+        fs::create_dir(dir.join("elsewhere")).unwrap();
+        fs::write(dir.join("elsewhere/target.txt"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("elsewhere/target.txt"), dir.join("link")).unwrap();
+        let arguments = run_arguments(&[dir.join("link").to_str().unwrap()]);
+        assert_eq!(arguments.start_dir, dir);
+        assert_eq!(arguments.start_entry, Some("link".to_string()));
+        // Even a dangling one
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("dangling")).unwrap();
+        let arguments = run_arguments(&[dir.join("dangling").to_str().unwrap()]);
+        assert_eq!(arguments.start_entry, Some("dangling".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_directory_of_a_path_is_resolved() {
+        let dir = test_dir("resolved");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/file"), "").unwrap();
+        let path = dir.join("sub/../sub/./file");
+        let arguments = run_arguments(&[path.to_str().unwrap()]);
+        assert_eq!(arguments.start_dir, dir.join("sub"));
+        assert_eq!(arguments.start_entry, Some("file".to_string()));
+        assert_eq!(run_arguments(&[dir.join("sub/..").to_str().unwrap()]).start_dir, dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shell_outputs_are_written() {
+        let dir = test_dir("outputs");
+        let outputs = ShellOutputs {
+            choosedir: Some(dir.join("dir")),
+            selectfile: Some(dir.join("selection")),
+        };
+        let state = ExitState {
+            dir: PathBuf::from("/some/where"),
+            chosen: vec![PathBuf::from("/some/where/a"), PathBuf::from("/some/where/b c")],
+        };
+        write_shell_outputs(&outputs, &state).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("dir")).unwrap(), "/some/where\n");
+        assert_eq!(fs::read_to_string(dir.join("selection")).unwrap(), "/some/where/a\n/some/where/b c\n");
+
+        // Nothing chosen leaves an empty file, no options write nothing
+        let state = ExitState { dir: PathBuf::from("/"), chosen: Vec::new() };
+        write_shell_outputs(&outputs, &state).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("selection")).unwrap(), "");
+        let nowhere = ShellOutputs { choosedir: None, selectfile: None };
+        write_shell_outputs(&nowhere, &state).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("dir")).unwrap(), "/\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shell_outputs_report_what_could_not_be_written() {
+        let outputs = ShellOutputs { choosedir: Some(PathBuf::from("/surely/not/there/dir")), selectfile: None };
+        let state = ExitState { dir: PathBuf::from("/"), chosen: Vec::new() };
+        assert!(write_shell_outputs(&outputs, &state).unwrap_err().starts_with("could not write /surely/not/there/dir: "));
+    }
+
+    #[test]
+    fn shell_output_options_need_a_value() {
+        assert_eq!(error_of(&["--choosedir"]), "`--choosedir` needs a value");
+        let arguments = run_arguments(&["--choosedir", "/a", "--selectfile", "/b", "/"]);
+        assert_eq!(arguments.shell_outputs.choosedir, Some(PathBuf::from("/a")));
+        assert_eq!(arguments.shell_outputs.selectfile, Some(PathBuf::from("/b")));
+    }
+}
@@ -14,6 +14,10 @@ pub struct PaintSettings {
     pub file_paint: Paint,
     pub unknown_paint: Paint,
     pub executable_paint: Paint,
+    pub block_device_paint: Paint,
+    pub char_device_paint: Paint,
+    pub fifo_paint: Paint,
+    pub socket_paint: Paint,
 }
 
 pub fn maybe_selected_paint_from(paint: Paint, convert: bool) -> Paint {
@@ -27,10 +31,14 @@ pub fn maybe_selected_paint_from(paint: Paint, convert: bool) -> Paint {
 pub fn paint_for(entrytype: &EntryType, name: &str,
         executable: bool, paint_settings: &PaintSettings) -> Paint {
     match entrytype {
-        EntryType::Directory => paint_settings.dir_paint,
-        EntryType::Symlink   => paint_settings.symlink_paint,
-        EntryType::Unknown   => paint_settings.unknown_paint,
-        EntryType::Regular   =>
+        EntryType::Directory   => paint_settings.dir_paint,
+        EntryType::Symlink     => paint_settings.symlink_paint,
+        EntryType::BlockDevice => paint_settings.block_device_paint,
+        EntryType::CharDevice  => paint_settings.char_device_paint,
+        EntryType::Fifo        => paint_settings.fifo_paint,
+        EntryType::Socket      => paint_settings.socket_paint,
+        EntryType::Unknown     => paint_settings.unknown_paint,
+        EntryType::Regular     =>
             if let Some(paint) = maybe_paint_for_name(name) { paint }
             else if executable { paint_settings.executable_paint }
             else               { paint_settings.file_paint },
@@ -103,10 +111,10 @@ impl Paint {
         self
     }
 
-    // pub fn underlined(mut self) -> Self {
-    //     self.underlined = true;
-    //     self
-    // }
+    pub fn underlined(mut self) -> Self {
+        self.underlined = true;
+        self
+    }
 }
 
 fn get_rgb(color: Color) -> RGB {
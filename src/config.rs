@@ -0,0 +1,243 @@
+use crate::coloring::*;
+use crate::input::*;
+use crate::system::Settings;
+use crate::notification::Millis;
+use crate::transfer::ConflictPolicy;
+use crate::details::*;
+use crate::direntry::Sorting;
+use crate::matching::SearchMode;
+
+use std::path::PathBuf;
+use std::fs;
+
+
+// The config is a plain list of `key = value` lines. Empty lines and lines
+// starting with '#' are ignored. Paints are written as `fg bg [bold] [underlined]`.
+//
+//     columns_ratio = 2 3 3
+//     scrolling_gap = 4
+//     show_hidden = false
+//     sorting_type = time_modified
+//     dir_paint = cyan default bold
+//-----------------------------------------------------------------------------
+pub fn default_settings() -> Settings {
+    Settings {
+        columns_ratio: vec![2,3,3],
+        primary_paint: Paint::with_fg_bg(Color::White,  Color::Default),
+        preview_paint: Paint::with_fg_bg(Color::Green,  Color::Default),
+        paint_settings: PaintSettings {
+            dir_paint:          Paint::with_fg_bg(Color::Cyan,   Color::Default).bold(),
+            symlink_paint:      Paint::with_fg_bg(Color::Yellow, Color::Default).bold(),
+            file_paint:         Paint::with_fg_bg(Color::White,  Color::Default),
+            unknown_paint:      Paint::with_fg_bg(Color::Grey,   Color::White)  .bold(),
+            executable_paint:   Paint::with_fg_bg(Color::Green,  Color::Default).bold(),
+            block_device_paint: Paint::with_fg_bg(Color::Yellow, Color::Black)  .bold(),
+            char_device_paint:  Paint::with_fg_bg(Color::Yellow, Color::Black),
+            fifo_paint:         Paint::with_fg_bg(Color::Purple, Color::Default),
+            socket_paint:       Paint::with_fg_bg(Color::Purple, Color::Default).bold(),
+        },
+        scrolling_gap: 4,
+        copy_done_notification_delay_ms: 2000,
+        show_hidden: true,
+        sorting: Sorting {
+            sorting_type: SortingType::Lexicographically,
+            reverse: false,
+            directories_first: false,
+        },
+        sort_per_directory: false,
+        conflict_policy: ConflictPolicy::Ask,
+        confirm_trash: true,
+        confirm_delete: true,
+        confirm_move: true,
+        confirm_rename: true,
+        status_fields: default_status_fields(),
+        find_ignore: vec![".git".to_string()],
+        search_mode: SearchMode::Substring,
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        if !dir.is_empty() { return Some(PathBuf::from(dir).join("vffm")); }
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("vffm"))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_DATA_HOME") {
+        if !dir.is_empty() { return Some(PathBuf::from(dir).join("vffm")); }
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/vffm"))
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config"))
+}
+
+// Returns the Settings along with the list of problems found in the file.
+// A missing file is not a problem: the defaults are used silently.
+pub fn load_settings(path: Option<&PathBuf>) -> (Settings, Vec<String>) {
+    let mut settings = default_settings();
+    let mut errors = Vec::new();
+    if let Some(path) = path {
+        match fs::read_to_string(path) {
+            Ok(contents) => apply_config(&mut settings, &contents, &mut errors),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => errors.push(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+    (settings, errors)
+}
+
+fn apply_config(settings: &mut Settings, contents: &str, errors: &mut Vec<String>) {
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let line_number = index + 1;
+        if let Some((key, value)) = split_key_value(line) {
+            if let Err(message) = apply_option(settings, key, value) {
+                errors.push(format!("config:{}: {}", line_number, message));
+            }
+        } else {
+            errors.push(format!("config:{}: expected `key = value`", line_number));
+        }
+    }
+}
+
+pub fn split_key_value(line: &str) -> Option<(&str, &str)> {
+    let separator = line.find('=')?;
+    let (key, value) = line.split_at(separator);
+    let key = key.trim();
+    if key.is_empty() { return None; }
+    Some((key, value[1..].trim()))
+}
+
+fn apply_option(settings: &mut Settings, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "columns_ratio" => settings.columns_ratio = parse_columns_ratio(value)?,
+        "scrolling_gap" => settings.scrolling_gap = parse_number(value)? as usize,
+        "copy_done_notification_delay_ms" =>
+            settings.copy_done_notification_delay_ms = parse_number(value)? as Millis,
+        "show_hidden"      => settings.show_hidden  = parse_bool(value)?,
+        "sorting_type"     => settings.sorting.sorting_type = parse_sorting_type(value)?,
+        "sort_reverse"     => settings.sorting.reverse = parse_bool(value)?,
+        "sort_directories_first" => settings.sorting.directories_first = parse_bool(value)?,
+        "sort_per_directory"     => settings.sort_per_directory = parse_bool(value)?,
+        "paste_conflict_policy" => settings.conflict_policy = parse_conflict_policy(value)?,
+        "confirm_trash"    => settings.confirm_trash  = parse_bool(value)?,
+        "confirm_delete"   => settings.confirm_delete = parse_bool(value)?,
+        "confirm_move"     => settings.confirm_move   = parse_bool(value)?,
+        "confirm_rename"   => settings.confirm_rename = parse_bool(value)?,
+        "status_fields"    => settings.status_fields  = parse_status_fields(value)?,
+        "find_ignore"      => settings.find_ignore    = value.split_whitespace().map(String::from).collect(),
+        "search_mode"      => settings.search_mode    = parse_search_mode(value)?,
+        "primary_paint"      => settings.primary_paint = parse_paint(value)?,
+        "preview_paint"      => settings.preview_paint = parse_paint(value)?,
+        "dir_paint"          => settings.paint_settings.dir_paint          = parse_paint(value)?,
+        "symlink_paint"      => settings.paint_settings.symlink_paint      = parse_paint(value)?,
+        "file_paint"         => settings.paint_settings.file_paint         = parse_paint(value)?,
+        "unknown_paint"      => settings.paint_settings.unknown_paint      = parse_paint(value)?,
+        "executable_paint"   => settings.paint_settings.executable_paint   = parse_paint(value)?,
+        "block_device_paint" => settings.paint_settings.block_device_paint = parse_paint(value)?,
+        "char_device_paint"  => settings.paint_settings.char_device_paint  = parse_paint(value)?,
+        "fifo_paint"         => settings.paint_settings.fifo_paint         = parse_paint(value)?,
+        "socket_paint"       => settings.paint_settings.socket_paint       = parse_paint(value)?,
+        _ => return Err(format!("unknown option `{}`", key)),
+    }
+    Ok(())
+}
+//-----------------------------------------------------------------------------
+fn parse_number(value: &str) -> Result<u64, String> {
+    value.parse::<u64>().map_err(|_| format!("`{}` is not a non-negative number", value))
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true"  | "yes" | "on"  => Ok(true),
+        "false" | "no"  | "off" => Ok(false),
+        _ => Err(format!("`{}` is not a boolean (expected true or false)", value)),
+    }
+}
+
+fn parse_columns_ratio(value: &str) -> Result<Vec<u32>, String> {
+    let ratio = value.split_whitespace()
+        .map(|part| part.parse::<u32>().map_err(|_| format!("`{}` is not a valid ratio part", part)))
+        .collect::<Result<Vec<u32>, String>>()?;
+    if ratio.len() != 3 {
+        return Err(format!("columns_ratio needs exactly 3 parts, got {}", ratio.len()));
+    }
+    if ratio.contains(&0) {
+        return Err("columns_ratio parts must be positive".to_string());
+    }
+    Ok(ratio)
+}
+
+pub fn parse_sorting_type(value: &str) -> Result<SortingType, String> {
+    match value {
+        "lexicographically" => Ok(SortingType::Lexicographically),
+        "case_insensitive"  => Ok(SortingType::CaseInsensitive),
+        "natural"           => Ok(SortingType::Natural),
+        "extension"         => Ok(SortingType::Extension),
+        "size"              => Ok(SortingType::Size),
+        "time_modified"     => Ok(SortingType::TimeModified),
+        "time_changed"      => Ok(SortingType::TimeChanged),
+        "time_accessed"     => Ok(SortingType::TimeAccessed),
+        "any"               => Ok(SortingType::Any),
+        _ => Err(format!("unknown sorting type `{}`", value)),
+    }
+}
+
+fn parse_search_mode(value: &str) -> Result<SearchMode, String> {
+    match value {
+        "substring" => Ok(SearchMode::Substring),
+        "fuzzy"     => Ok(SearchMode::Fuzzy),
+        "regex"     => Ok(SearchMode::Regex),
+        "glob"      => Ok(SearchMode::Glob),
+        "prefix"    => Ok(SearchMode::Prefix),
+        _ => Err(format!("unknown search mode `{}`", value)),
+    }
+}
+
+fn parse_conflict_policy(value: &str) -> Result<ConflictPolicy, String> {
+    match value {
+        "ask"        => Ok(ConflictPolicy::Ask),
+        "overwrite"  => Ok(ConflictPolicy::Overwrite),
+        "skip"       => Ok(ConflictPolicy::Skip),
+        "rename"     => Ok(ConflictPolicy::Rename),
+        "keep_newer" => Ok(ConflictPolicy::KeepNewer),
+        "merge"      => Ok(ConflictPolicy::Merge),
+        _ => Err(format!("unknown paste conflict policy `{}`", value)),
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    match value {
+        "black"      => Ok(Color::Black),
+        "red"        => Ok(Color::Red),
+        "green"      => Ok(Color::Green),
+        "light_blue" => Ok(Color::LightBlue),
+        "yellow"     => Ok(Color::Yellow),
+        "blue"       => Ok(Color::Blue),
+        "purple"     => Ok(Color::Purple),
+        "cyan"       => Ok(Color::Cyan),
+        "white"      => Ok(Color::White),
+        "grey"       => Ok(Color::Grey),
+        "default"    => Ok(Color::Default),
+        _ => Err(format!("unknown color `{}`", value)),
+    }
+}
+
+fn parse_paint(value: &str) -> Result<Paint, String> {
+    let mut parts = value.split_whitespace();
+    let fg = parse_color(parts.next().ok_or("paint needs a foreground color")?)?;
+    let bg = parse_color(parts.next().ok_or("paint needs a background color")?)?;
+    let mut paint = Paint::with_fg_bg(fg, bg);
+    for attribute in parts {
+        match attribute {
+            "bold"       => paint = paint.bold(),
+            "underlined" => paint = paint.underlined(),
+            _ => return Err(format!("unknown paint attribute `{}`", attribute)),
+        }
+    }
+    Ok(paint)
+}
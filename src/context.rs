@@ -4,8 +4,9 @@ use crate::right_column::*;
 use crate::input_mode::*;
 use crate::drawing::*;
 use crate::coloring::*;
-use crate::input::*;
 use crate::utils::*;
+use crate::details::*;
+use crate::matching::NameFilter;
 
 use std::path::PathBuf;
 
@@ -25,7 +26,7 @@ pub struct Context {
     pub parent_siblings_shift: usize, // depends on display_settings
     pub current_siblings_shift: usize, // depends on display_settings
 
-    pub current_permissions: Option<String>,
+    pub current_details: Option<Details>,
     pub additional_entry_info: Option<String>,
     pub cumulative_size_text: Option<String>,
 
@@ -36,17 +37,19 @@ impl Context {
     pub fn generate(parent_path: PathBuf,
                     display_settings: &DisplaySettings,
                     paint_settings: &PaintSettings,
-                    sorting_type: &SortingType,
+                    sorting_for: &dyn Fn(&PathBuf) -> Sorting,
+                    filter: Option<&NameFilter>,
                     include_hidden: bool,
                     selected: &Vec<PathBuf>) -> Context {
         let current_siblings = into_sorted_direntries(
             collect_maybe_dir(&parent_path, None, include_hidden),
-            paint_settings, sorting_type,
+            paint_settings, &sorting_for(&parent_path), filter,
             selected, Some(&parent_path));
+        let grandparent = maybe_parent(&parent_path);
         let parent_siblings = into_sorted_direntries(
             collect_siblings_of(&parent_path, include_hidden),
-            paint_settings, sorting_type, selected,
-            maybe_parent(&parent_path).as_ref());
+            paint_settings, &sorting_for(grandparent.as_ref().unwrap_or(&parent_path)), filter,
+            selected, grandparent.as_ref());
         let first_entry_path = path_of_nth_entry_inside(0, &parent_path, &current_siblings);
         let first_entry_ref = nth_entry_inside(0, &current_siblings);
         let parent_index = index_of_entry_inside(&parent_path, &parent_siblings).unwrap();
@@ -55,8 +58,8 @@ impl Context {
         let (begin, end) = display_settings.columns_coord[column_index];
         let column_width = (end - begin) as usize;
         let right_column = RightColumn::collect(
-            &first_entry_path, paint_settings, sorting_type, include_hidden,
-            display_settings.column_effective_height, column_width, selected);
+            &first_entry_path, paint_settings, sorting_for, filter, include_hidden,
+            (display_settings.column_effective_height, column_width), selected);
         let parent_siblings_shift = siblings_shift_for(
             display_settings.scrolling_gap,
             display_settings.column_effective_height,
@@ -70,7 +73,7 @@ impl Context {
             parent_index,
             current_index,
             parent_siblings,
-            current_permissions: string_permissions_for_entry(&first_entry_ref),
+            current_details: first_entry_path.as_ref().and_then(|path| details_of(path).ok()),
             additional_entry_info: get_additional_entry_info(first_entry_ref, &first_entry_path),
             current_siblings,
             right_column,
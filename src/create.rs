@@ -0,0 +1,183 @@
+use crate::filesystem::*;
+
+use std::path::{Path, PathBuf, Component};
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::symlink;
+
+
+// The typed text is a path relative to the current directory (or an absolute one).
+// Links to several entries are placed inside the typed directory under their names.
+//-----------------------------------------------------------------------------
+#[derive(Clone)]
+pub enum CreationKind {
+    File,
+    Directory, // along with the missing parents
+    Symlink { targets: Vec<PathBuf>, relative: bool },
+    HardLink(Vec<PathBuf>),
+}
+
+pub fn creation_prompt(kind: &CreationKind) -> String {
+    match kind {
+        CreationKind::File      => "new file: ".to_string(),
+        CreationKind::Directory => "new directory: ".to_string(),
+        CreationKind::Symlink { targets, relative } => {
+            let what = if *relative { "relative symlink" } else { "symlink" };
+            if targets.len() == 1 { format!("{} to {}: ", what, file_name(&targets[0])) }
+            else                  { format!("{}s to {} entries in: ", what, targets.len()) }
+        },
+        CreationKind::HardLink(targets) => {
+            if targets.len() == 1 { format!("hard link to {}: ", file_name(&targets[0])) }
+            else                  { format!("hard links to {} entries in: ", targets.len()) }
+        },
+    }
+}
+
+// What the prompt starts with
+pub fn initial_text_of(kind: &CreationKind) -> String {
+    match kind {
+        CreationKind::Symlink { targets, .. } | CreationKind::HardLink(targets)
+            if targets.len() == 1 => file_name(&targets[0]),
+        _ => String::new(),
+    }
+}
+
+// Returns the created entries, for a directory the topmost one that did not exist,
+// along with what went wrong. Linking stops at the first failure.
+pub fn create(kind: &CreationKind, parent_path: &Path, text: &str) -> (Vec<PathBuf>, Option<String>) {
+    if text.is_empty() && !is_multiple_link(kind) { return (Vec::new(), Some("Empty name".to_string())); }
+    let path = normalize(&parent_path.join(text));
+    let result = match kind {
+        CreationKind::File => OpenOptions::new().write(true).create_new(true).open(&path)
+            .map(|_| vec![path.clone()])
+            .map_err(|e| format!("Could not create {}: {}", path.display(), e)),
+        CreationKind::Directory => {
+            let topmost = topmost_missing(&path);
+            if fs::symlink_metadata(&path).is_ok() {
+                Err(format!("{} already exists", path.display()))
+            } else {
+                fs::create_dir_all(&path).map(|_| vec![topmost])
+                    .map_err(|e| format!("Could not create {}: {}", path.display(), e))
+            }
+        },
+        CreationKind::Symlink { targets, relative } => return link_all(targets, &path, |target, link| {
+            let target = if *relative { relative_path(link.parent().unwrap(), target) }
+                         else         { target.clone() };
+            symlink(&target, link)
+        }),
+        CreationKind::HardLink(targets) => return link_all(targets, &path, |target, link| {
+            fs::hard_link(target, link)
+        }),
+    };
+    match result {
+        Ok(created) => (created, None),
+        Err(error)  => (Vec::new(), Some(error)),
+    }
+}
+
+fn is_multiple_link(kind: &CreationKind) -> bool {
+    match kind {
+        CreationKind::Symlink { targets, .. } | CreationKind::HardLink(targets) => targets.len() > 1,
+        _ => false,
+    }
+}
+
+fn link_all<F>(targets: &[PathBuf], path: &Path, make_link: F) -> (Vec<PathBuf>, Option<String>)
+        where F: Fn(&PathBuf, &PathBuf) -> std::io::Result<()> {
+    if targets.len() > 1 && !path.is_dir() {
+        return (Vec::new(), Some(format!("{} is not a directory", path.display())));
+    }
+    let mut created = Vec::new();
+    for target in targets.iter() {
+        let link = if targets.len() == 1 { path.to_path_buf() }
+                   else                  { path.join(target.file_name().unwrap()) };
+        if let Err(e) = make_link(target, &link) {
+            let error = format!("Could not link {} to {}: {}", link.display(), target.display(), e);
+            return (created, Some(error));
+        }
+        created.push(link);
+    }
+    (created, None)
+}
+
+// Resolves `.` and `..` without touching the filesystem, the path is absolute
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir    => {},
+            Component::ParentDir => { result.pop(); },
+            other                => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+fn topmost_missing(path: &Path) -> PathBuf {
+    let mut topmost = path.to_path_buf();
+    while let Some(parent) = topmost.parent() {
+        if fs::symlink_metadata(parent).is_ok() { break; }
+        topmost = parent.to_path_buf();
+    }
+    topmost
+}
+
+// E.g. from /a/b/c to /a/d/e is ../../d/e
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(to_components.iter()).take_while(|(a, b)| a == b).count();
+    let mut result = PathBuf::new();
+    for _ in common..from.len() { result.push(".."); }
+    for component in to_components[common..].iter() { result.push(component.as_os_str()); }
+    if result.as_os_str().is_empty() { result.push("."); }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(path: &str) -> PathBuf {
+        normalize(&PathBuf::from(path))
+    }
+
+    fn relative(from_dir: &str, to: &str) -> PathBuf {
+        relative_path(&PathBuf::from(from_dir), &PathBuf::from(to))
+    }
+
+    #[test]
+    fn normalize_resolves_the_dots() {
+        assert_eq!(normalized("/a/b/c"), PathBuf::from("/a/b/c"));
+        assert_eq!(normalized("/a/./b/."), PathBuf::from("/a/b"));
+        assert_eq!(normalized("/a/b/../c"), PathBuf::from("/a/c"));
+        assert_eq!(normalized("/a/b/../../c/../d"), PathBuf::from("/d"));
+        assert_eq!(normalized("/a//b/"), PathBuf::from("/a/b"));
+    }
+
+    #[test]
+    fn normalize_stops_at_the_root() {
+        assert_eq!(normalized("/.."), PathBuf::from("/"));
+        assert_eq!(normalized("/a/../../.."), PathBuf::from("/"));
+        assert_eq!(normalized("/../../a"), PathBuf::from("/a"));
+    }
+
+    #[test]
+    fn relative_path_goes_up_to_the_common_part() {
+        assert_eq!(relative("/a/b/c", "/a/d/e"), PathBuf::from("../../d/e"));
+        assert_eq!(relative("/a/b", "/a/b/c"), PathBuf::from("c"));
+        assert_eq!(relative("/a/b", "/a/c"), PathBuf::from("../c"));
+        assert_eq!(relative("/a/b/c", "/a"), PathBuf::from("../.."));
+        assert_eq!(relative("/a", "/x/y"), PathBuf::from("../x/y"));
+    }
+
+    #[test]
+    fn relative_path_to_the_same_directory_is_a_dot() {
+        assert_eq!(relative("/a/b", "/a/b"), PathBuf::from("."));
+        assert_eq!(relative("/", "/"), PathBuf::from("."));
+    }
+
+    #[test]
+    fn relative_path_compares_whole_components() {
+        assert_eq!(relative("/a/bc", "/a/b/c"), PathBuf::from("../b/c"));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::filesystem::*;
+use crate::permissions::{user_name, group_name};
+use crate::utils::*;
+
+use std::path::PathBuf;
+use std::fs;
+use std::time::UNIX_EPOCH;
+use std::os::unix::fs::MetadataExt;
+
+
+// What `stat` tells about an entry, read once when the cursor lands on it
+//-----------------------------------------------------------------------------
+#[derive(Clone)]
+pub struct Details {
+    pub path: PathBuf,
+    pub entrytype: EntryType,
+    pub permissions: String,
+    pub mode: u32,
+    pub owner: String,
+    pub uid: u32,
+    pub group: String,
+    pub gid: u32,
+    pub size: u64,
+    pub blocks: u64,
+    pub block_size: u64,
+    pub links: u64,
+    pub inode: u64,
+    pub device: u64,
+    pub special_device: u64, // of a block or char device
+    pub accessed: i64,
+    pub modified: i64,
+    pub changed: i64,
+    pub born: Option<i64>, // not every filesystem knows it
+    pub symlink_target: Option<PathBuf>,
+}
+
+pub fn details_of(path: &PathBuf) -> Result<Details, String> {
+    let metadata = fs::symlink_metadata(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(Details {
+        path: path.clone(),
+        entrytype: entry_type_from(metadata.file_type()),
+        permissions: permissions_from_metadata(&metadata).string_representation(),
+        mode: metadata.mode() & 0o7777,
+        owner: user_name(metadata.uid()),
+        uid: metadata.uid(),
+        group: group_name(metadata.gid()),
+        gid: metadata.gid(),
+        size: metadata.size(),
+        blocks: metadata.blocks(),
+        block_size: metadata.blksize(),
+        links: metadata.nlink(),
+        inode: metadata.ino(),
+        device: metadata.dev(),
+        special_device: metadata.rdev(),
+        accessed: metadata.atime(),
+        modified: metadata.mtime(),
+        changed: metadata.ctime(),
+        born: metadata.created().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64),
+        symlink_target: fs::read_link(path).ok(),
+    })
+}
+
+// The rows of the popup, in the order of `stat`
+pub fn details_rows(details: &Details) -> Vec<(String, String)> {
+    let now = now_seconds();
+    let time = |seconds: i64| format!("{}  ({})", local_date_time(seconds, ' '), relative_time(seconds, now));
+    let mut rows = Vec::new();
+    let mut row = |key: &str, value: String| rows.push((key.to_string(), value));
+    match details.symlink_target.as_ref() {
+        Some(target) => row("File", format!("{} -> {}", details.path.display(), target.display())),
+        None         => row("File", path_to_string(&details.path)),
+    }
+    row("Type", type_name_of(&details.entrytype).to_string());
+    row("Size", format!("{} bytes ({})", details.size, human_size(details.size)));
+    row("Blocks", format!("{} of 512 bytes, {} bytes per IO block", details.blocks, details.block_size));
+    row("Device", format!("{}  (inode {}, {})", device_numbers(details.device),
+                          details.inode, links_text(details.links)));
+    if details.entrytype == EntryType::BlockDevice || details.entrytype == EntryType::CharDevice {
+        row("Device type", device_numbers(details.special_device));
+    }
+    row("Access", format!("{:04o}  {}", details.mode, details.permissions));
+    row("Owner", format!("{}  (uid {})", details.owner, details.uid));
+    row("Group", format!("{}  (gid {})", details.group, details.gid));
+    row("Accessed", time(details.accessed));
+    row("Modified", time(details.modified));
+    row("Changed", time(details.changed));
+    row("Born", details.born.map(time).unwrap_or("unknown".to_string()));
+    rows
+}
+
+fn type_name_of(entrytype: &EntryType) -> &'static str {
+    match entrytype {
+        EntryType::Regular     => "regular file",
+        EntryType::Directory   => "directory",
+        EntryType::Symlink     => "symbolic link",
+        EntryType::BlockDevice => "block special file",
+        EntryType::CharDevice  => "character special file",
+        EntryType::Fifo        => "fifo",
+        EntryType::Socket      => "socket",
+        EntryType::Unknown     => "unknown",
+    }
+}
+
+fn links_text(links: u64) -> String {
+    if links == 1 { "1 link".to_string() } else { format!("{} links", links) }
+}
+
+// E.g. "8,1" for the major and minor numbers, split the way glibc does it
+fn device_numbers(device: u64) -> String {
+    let major = ((device >> 32) & 0xffff_f000) | ((device >> 8) & 0xfff);
+    let minor = ((device >> 12) & 0xffff_ff00) | (device & 0xff);
+    format!("{},{}", major, minor)
+}
+//-----------------------------------------------------------------------------
+// What the bottom bar may show about the current entry, in the configured order
+//
+//     status_fields = permissions size owner modified_ago
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusField {
+    Permissions,
+    Size,
+    Owner,
+    Group,
+    Links,
+    Inode,
+    Device,
+    Time(TimeKind, bool), // relative, e.g. "3h ago"
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeKind {
+    Accessed,
+    Modified,
+    Changed,
+    Born,
+}
+
+pub fn default_status_fields() -> Vec<StatusField> {
+    vec![StatusField::Permissions, StatusField::Size]
+}
+
+pub fn parse_status_fields(value: &str) -> Result<Vec<StatusField>, String> {
+    value.split_whitespace().map(|name| {
+        let (name, relative) = match name.strip_suffix("_ago") {
+            Some(name) => (name, true),
+            None       => (name, false),
+        };
+        let time = |kind| Ok(StatusField::Time(kind, relative));
+        match name {
+            "accessed" => time(TimeKind::Accessed),
+            "modified" => time(TimeKind::Modified),
+            "changed"  => time(TimeKind::Changed),
+            "born"     => time(TimeKind::Born),
+            _ if relative => Err(format!("`{}_ago` is not a time", name)),
+            "permissions" => Ok(StatusField::Permissions),
+            "size"        => Ok(StatusField::Size),
+            "owner"       => Ok(StatusField::Owner),
+            "group"       => Ok(StatusField::Group),
+            "links"       => Ok(StatusField::Links),
+            "inode"       => Ok(StatusField::Inode),
+            "device"      => Ok(StatusField::Device),
+            _ => Err(format!("unknown status field `{}`", name)),
+        }
+    }).collect()
+}
+
+pub fn status_field_text(details: &Details, field: StatusField, now: i64) -> String {
+    match field {
+        StatusField::Permissions => details.permissions.clone(),
+        StatusField::Size        => human_size(details.size),
+        StatusField::Owner       => details.owner.clone(),
+        StatusField::Group       => details.group.clone(),
+        StatusField::Links       => links_text(details.links),
+        StatusField::Inode       => format!("inode {}", details.inode),
+        StatusField::Device      => device_numbers(details.device),
+        StatusField::Time(kind, relative) => {
+            let seconds = match kind {
+                TimeKind::Accessed => Some(details.accessed),
+                TimeKind::Modified => Some(details.modified),
+                TimeKind::Changed  => Some(details.changed),
+                TimeKind::Born     => details.born,
+            };
+            match seconds {
+                Some(seconds) if relative => relative_time(seconds, now),
+                Some(seconds)             => local_date_time(seconds, ' '),
+                None                      => "born ?".to_string(),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_of(value: &str) -> String {
+        match parse_status_fields(value) {
+            Ok(_)      => panic!("`{}` should not parse", value),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn parse_status_fields_keeps_the_order() {
+        assert_eq!(parse_status_fields("permissions size"), Ok(default_status_fields()));
+        assert_eq!(parse_status_fields("  inode   owner group\tlinks device size "), Ok(vec![
+            StatusField::Inode, StatusField::Owner, StatusField::Group,
+            StatusField::Links, StatusField::Device, StatusField::Size,
+        ]));
+        assert_eq!(parse_status_fields(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn parse_status_fields_reads_the_times() {
+        assert_eq!(parse_status_fields("accessed modified changed born"), Ok(vec![
+            StatusField::Time(TimeKind::Accessed, false), StatusField::Time(TimeKind::Modified, false),
+            StatusField::Time(TimeKind::Changed, false),  StatusField::Time(TimeKind::Born, false),
+        ]));
+        assert_eq!(parse_status_fields("modified_ago born_ago"), Ok(vec![
+            StatusField::Time(TimeKind::Modified, true), StatusField::Time(TimeKind::Born, true),
+        ]));
+    }
+
+    #[test]
+    fn parse_status_fields_rejects_unknown_names() {
+        assert_eq!(error_of("size bogus"), "unknown status field `bogus`");
+        assert_eq!(error_of("Size"), "unknown status field `Size`");
+        assert_eq!(error_of("_ago"), "`_ago` is not a time");
+        assert_eq!(error_of("size_ago"), "`size_ago` is not a time");
+        assert_eq!(error_of("modified_ago_ago"), "`modified_ago_ago` is not a time");
+    }
+}
@@ -1,7 +1,11 @@
 use crate::filesystem::*;
 use crate::coloring::*;
 use crate::input::*;
+use crate::matching::NameFilter;
 use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
 
 #[derive(Clone)]
 pub struct DirEntry {
@@ -9,10 +13,12 @@ pub struct DirEntry {
     pub name: String,
     pub size: u64,
     pub time_modified: u64,
-    pub permissions: Permissions,
+    pub time_changed: u64,
+    pub time_accessed: u64,
 
     pub paint: Paint,
     pub is_selected: bool,
+    pub highlighted: Vec<usize>, // chars of the name matched by the search
 }
 
 impl DirEntry {
@@ -24,9 +30,11 @@ impl DirEntry {
             name:          entry.name,
             size:          entry.size,
             time_modified: entry.time_modified,
-            permissions:   entry.permissions,
+            time_changed:  entry.time_changed,
+            time_accessed: entry.time_accessed,
             paint,
             is_selected,
+            highlighted: Vec::new(),
         }
     }
 
@@ -96,26 +104,129 @@ pub fn is_selected(selected: &Vec<PathBuf>, name: &str, parent_path: Option<&Pat
 }
 
 
+//-----------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Sorting {
+    pub sorting_type: SortingType,
+    pub reverse: bool,
+    pub directories_first: bool, // not affected by `reverse`
+}
+
+// E.g. "natural, reversed, dirs first"
+pub fn sorting_description(sorting: &Sorting) -> String {
+    let name = match sorting.sorting_type {
+        SortingType::Lexicographically => "name",
+        SortingType::CaseInsensitive   => "name (case insensitive)",
+        SortingType::Natural           => "natural",
+        SortingType::Extension         => "extension",
+        SortingType::Size              => "size",
+        SortingType::TimeModified      => "modified",
+        SortingType::TimeChanged       => "changed",
+        SortingType::TimeAccessed      => "accessed",
+        SortingType::Any               => "unsorted",
+    };
+    let mut text = name.to_string();
+    if sorting.reverse { text += ", reversed"; }
+    if sorting.directories_first { text += ", dirs first"; }
+    text
+}
+
 pub fn into_sorted_direntries(entries: Vec<Entry>,
                               paint_settings: &PaintSettings,
-                              sorting_type: &SortingType,
+                              sorting: &Sorting,
+                              filter: Option<&NameFilter>,
                               selected: &Vec<PathBuf>,
                               parent_path: Option<&PathBuf>) -> Vec<DirEntry> {
+    let entries = match filter {
+        Some(filter) => entries.into_iter()
+            .filter(|entry| passes_filter(entry, filter, parent_path)).collect(),
+        None => entries,
+    };
     let entries = into_direntries(entries, paint_settings, selected, parent_path);
-    sort(entries, sorting_type)
+    sort(entries, sorting)
+}
+
+// Directories and the symlinks to them pass, to keep the way down open
+fn passes_filter(entry: &Entry, filter: &NameFilter, parent_path: Option<&PathBuf>) -> bool {
+    match entry.entrytype {
+        EntryType::Directory => true,
+        EntryType::Symlink if parent_path.is_none_or(|path| path.join(&entry.name).is_dir()) => true,
+        _ => filter.is_match(&entry.name),
+    }
 }
 
-fn sort(mut entries: Vec<DirEntry>, sorting_type: &SortingType) -> Vec<DirEntry> {
-    match sorting_type {
-        SortingType::Lexicographically => entries.sort_by(
-            |a, b| a.name.cmp(&b.name)),
+// Ties are broken by the name, so that the order does not depend on the directory listing
+fn sort(mut entries: Vec<DirEntry>, sorting: &Sorting) -> Vec<DirEntry> {
+    let by_name = |a: &DirEntry, b: &DirEntry| a.name.cmp(&b.name);
+    match sorting.sorting_type {
+        SortingType::Lexicographically => entries.sort_by(by_name),
+        SortingType::CaseInsensitive => entries.sort_by(
+            |a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then_with(|| by_name(a, b))),
+        SortingType::Natural => entries.sort_by(
+            |a, b| natural_cmp(&a.name, &b.name).then_with(|| by_name(a, b))),
+        SortingType::Extension => entries.sort_by(
+            |a, b| extension_of(&a.name).cmp(extension_of(&b.name)).then_with(|| by_name(a, b))),
+        SortingType::Size => entries.sort_by(
+            |a, b| a.size.cmp(&b.size).then_with(|| by_name(a, b))),
         SortingType::TimeModified => entries.sort_by(
-            |a, b| a.time_modified.cmp(&b.time_modified)),
+            |a, b| a.time_modified.cmp(&b.time_modified).then_with(|| by_name(a, b))),
+        SortingType::TimeChanged => entries.sort_by(
+            |a, b| a.time_changed.cmp(&b.time_changed).then_with(|| by_name(a, b))),
+        SortingType::TimeAccessed => entries.sort_by(
+            |a, b| a.time_accessed.cmp(&b.time_accessed).then_with(|| by_name(a, b))),
         SortingType::Any => {},
     }
+    if sorting.reverse { entries.reverse(); }
+    if sorting.directories_first { entries.sort_by_key(|entry| !entry.is_dir()); } // stable
     entries
 }
 
+// A leading dot marks a hidden file rather than an extension
+fn extension_of(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(index) if index > 0 => &name[index + 1..],
+        _ => "",
+    }
+}
+
+// Runs of digits are compared by their value, the rest char by char
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None)    => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                // Without the leading zeros the longer number is the bigger one
+                let (x_trimmed, y_trimmed) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_trimmed.len().cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal { return ordering; }
+            },
+            (Some(x), Some(y)) => {
+                if x != y { return x.cmp(&y); }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if !c.is_ascii_digit() { break; }
+        number.push(c);
+        chars.next();
+    }
+    number
+}
+
 pub fn path_of_nth_entry_inside(n: usize, path: &PathBuf, entries: &Vec<DirEntry>) -> Option<PathBuf> {
     nth_entry_inside(n, entries).map(|entry| path.join(entry.name.clone()))
 }
@@ -131,9 +242,3 @@ pub fn index_of_entry_inside(path: &PathBuf, entries: &Vec<DirEntry>) -> Option<
     let sought_name = path.file_name().unwrap().to_str().unwrap();
     entries.iter().position(|direntry| direntry.name == sought_name)
 }
-
-pub fn string_permissions_for_entry(entry: &Option<&DirEntry>) -> Option<String> {
-    if let Some(entry) = entry {
-        Some(entry.permissions.string_representation())
-    } else { None }
-}
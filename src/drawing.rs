@@ -7,6 +7,11 @@ use crate::tab::*;
 use crate::notification::*;
 use crate::coloring::*;
 use crate::utils::*;
+use crate::transfer::*;
+use crate::create::creation_prompt;
+use crate::details::*;
+use crate::matching::*;
+use crate::select::select_prompt;
 
 use std::path::PathBuf;
 use pancurses::{Window,
@@ -193,30 +198,59 @@ impl Renderer {
         }
     }
 
-    pub fn draw_left_column(&self, mut cs: &mut ColorSystem,
-            siblings: &Vec<DirEntry>, index: usize, shift: usize) {
+    pub fn draw_left_column(&self, cs: &mut ColorSystem,
+            siblings: &[DirEntry], index: usize, shift: usize) {
         const COLUMN_INDEX: usize = 0;
-        self.list_entries(&mut cs, COLUMN_INDEX, siblings, Some(index), shift);
+        self.list_entries(cs, COLUMN_INDEX, siblings, Some(index), shift, None);
     }
 
-    pub fn draw_middle_column(&self, mut cs: &mut ColorSystem, inside_empty_dir: bool,
-                              siblings: &Vec<DirEntry>, index: usize, shift: usize) {
+    // The pending range is the one of the visual mode, from the anchor to the cursor
+    pub fn draw_middle_column(&self, cs: &mut ColorSystem, inside_empty_dir: bool,
+                              siblings: &[DirEntry], index: usize, shift: usize,
+                              pending: Option<(usize, usize)>) {
         const COLUMN_INDEX: usize = 1;
         if inside_empty_dir {
-            self.draw_empty_sign(&mut cs, COLUMN_INDEX);
+            self.draw_empty_sign(cs, COLUMN_INDEX);
         } else {
-            self.list_entries(&mut cs, COLUMN_INDEX, siblings, Some(index), shift);
+            self.list_entries(cs, COLUMN_INDEX, siblings, Some(index), shift, pending);
         }
     }
 
-    pub fn draw_right_column(&self, mut cs: &mut ColorSystem, right_column: &RightColumn, preview_paint: Paint) {
+    pub fn draw_rename_preview(&self, cs: &mut ColorSystem, rows: &[(String, Paint)]) {
+        const COLUMN_INDEX: usize = 1;
+        let (begin, end) = self.display_settings.columns_coord[COLUMN_INDEX];
+        let y = self.display_settings.entries_display_begin;
+        for (i, (text, paint)) in rows.iter().enumerate()
+                .take(self.display_settings.column_effective_height) {
+            cs.set_paint(&self.window, *paint);
+            mvprintw(&self.window, y + i as Coord, begin + 1, &maybe_truncate(text, (end - begin) as usize));
+        }
+    }
+
+    // Lines that are not entries, e.g. the matches of a grep, in place of the siblings
+    pub fn draw_rows_with_cursor(&self, cs: &mut ColorSystem, rows: &[String],
+            index: usize, shift: usize, paint: Paint, show_cursor: bool) {
+        const COLUMN_INDEX: usize = 1;
+        let (begin, end) = self.display_settings.columns_coord[COLUMN_INDEX];
+        let width = (end - begin - 1) as usize;
+        let y = self.display_settings.entries_display_begin;
+        for (i, row) in rows.iter().enumerate().skip(shift)
+                .take(self.display_settings.column_effective_height) {
+            cs.set_paint(&self.window, maybe_selected_paint_from(paint, show_cursor && i == index));
+            let text = maybe_truncate(row, width);
+            let padding = width - chars_amount(&text);
+            mvprintw(&self.window, y + (i - shift) as Coord, begin + 1, &(text + &" ".repeat(padding)));
+        }
+    }
+
+    pub fn draw_right_column(&self, cs: &mut ColorSystem, right_column: &RightColumn, preview_paint: Paint) {
         const COLUMN_INDEX: usize = 2;
         if let Some(siblings) = right_column.siblings_ref() {
             // Have siblings (Some or None) => are sure to be inside a dir or symlink
             if siblings.is_empty() {
-                self.draw_empty_sign(&mut cs, COLUMN_INDEX);
+                self.draw_empty_sign(cs, COLUMN_INDEX);
             } else {
-                self.list_entries(&mut cs, COLUMN_INDEX, siblings, None, 0);
+                self.list_entries(cs, COLUMN_INDEX, siblings, None, 0, None);
             }
         } else if let Some(preview) = right_column.preview_ref() {
             let (begin, _) = self.display_settings.columns_coord[COLUMN_INDEX];
@@ -242,11 +276,37 @@ impl Renderer {
 
     pub fn maybe_draw_input_mode(&self, cs: &mut ColorSystem, bar: &mut Bar, input_mode: &Option<InputMode>) {
         match input_mode.as_ref() {
-            Some(InputMode::Search(SearchTools {query, ..})) => {
+            Some(InputMode::Search(SearchTools {query, mode, ..})) => {
                 cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
                 bar.draw_left(&self.window, "/", 0);
                 cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
                 bar.draw_left(&self.window, query, 2);
+                if *mode != SearchMode::Substring {
+                    let (color, label) = if query_is_valid(*mode, query) {
+                        (Color::Yellow, format!("[{}]", search_mode_name(*mode)))
+                    } else { (Color::Red, format!("[{}, invalid]", search_mode_name(*mode))) };
+                    cs.set_paint(&self.window, Paint::with_fg_bg(color, Color::Default));
+                    bar.draw_left(&self.window, &label, 2);
+                }
+            },
+            Some(InputMode::Find(FindTools {query, searching, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+                bar.draw_left(&self.window, "find: ", 0);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
+                bar.draw_left(&self.window, query, 2);
+                if *searching {
+                    cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default));
+                    bar.draw_left(&self.window, "searching...", 2);
+                }
+            },
+            Some(InputMode::Grep(GrepTools {query, matches, searching, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+                bar.draw_left(&self.window, "grep: ", 0);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
+                bar.draw_left(&self.window, query, 2);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default));
+                if *searching { bar.draw_left(&self.window, "searching...", 2); }
+                if !matches.is_empty() { bar.draw_left(&self.window, &format!("{} matches", matches.len()), 2); }
             },
             Some(InputMode::ChangeName(ChangeNameTools {new_name, ..})) => {
                 cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
@@ -260,17 +320,51 @@ impl Renderer {
                 cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
                 bar.draw_left(&self.window, text, 2);
             },
+            Some(InputMode::Filter(FilterTools {text, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+                bar.draw_left(&self.window, "filter: ", 0);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
+                bar.draw_left(&self.window, text, 2);
+            },
+            Some(InputMode::Select(SelectTools {text, select, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+                bar.draw_left(&self.window, select_prompt(*select), 0);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
+                bar.draw_left(&self.window, text, 2);
+            },
+            Some(InputMode::PatternRename(PatternRenameTools {pattern, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+                bar.draw_left(&self.window, "rename: ", 0);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
+                bar.draw_left(&self.window, pattern, 2);
+            },
+            Some(InputMode::Create(CreateTools {kind, text, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+                bar.draw_left(&self.window, &creation_prompt(kind), 0);
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Purple, Color::Default));
+                bar.draw_left(&self.window, text, 2);
+            },
+            Some(InputMode::Confirm(ConfirmTools {question, ..})) => {
+                cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default).bold());
+                bar.draw_left(&self.window, &format!("{} [y/N]", question), 2);
+            },
             _ => {},
         }
     }
 
     pub fn maybe_draw_input_mode_cursor(&self, input_mode: &Option<InputMode>) {
         match input_mode.as_ref() {
-            Some(InputMode::Search(SearchTools {cursor_index, ..})) => {
-                if let Some(index) = cursor_index {
-                    const PREFIX_LEN: i32 = "/".len() as i32;
-                    self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *index as Coord);
-                }
+            Some(InputMode::Search(SearchTools {cursor_index: Some(index), ..})) => {
+                const PREFIX_LEN: i32 = "/".len() as i32;
+                self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *index as Coord);
+            },
+            Some(InputMode::Find(FindTools {cursor_index: Some(index), ..})) => {
+                const PREFIX_LEN: i32 = "find: ".len() as i32;
+                self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *index as Coord);
+            },
+            Some(InputMode::Grep(GrepTools {cursor_index: Some(index), ..})) => {
+                const PREFIX_LEN: i32 = "grep: ".len() as i32;
+                self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *index as Coord);
             },
             Some(InputMode::ChangeName(ChangeNameTools {cursor_index, ..})) => {
                 const PREFIX_LEN: i32 = "change to:".len() as i32;
@@ -280,22 +374,42 @@ impl Renderer {
                 const PREFIX_LEN: i32 = ":> ".len() as i32;
                 self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *cursor_index as Coord);
             }
+            Some(InputMode::Filter(FilterTools {cursor_index, ..})) => {
+                const PREFIX_LEN: i32 = "filter: ".len() as i32;
+                self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *cursor_index as Coord);
+            }
+            Some(InputMode::Select(SelectTools {select, cursor_index, ..})) => {
+                let prefix_len = select_prompt(*select).len() as Coord;
+                self.window.mv(self.display_settings.height - 1, prefix_len + *cursor_index as Coord);
+            }
+            Some(InputMode::PatternRename(PatternRenameTools {cursor_index, ..})) => {
+                const PREFIX_LEN: i32 = "rename: ".len() as i32;
+                self.window.mv(self.display_settings.height - 1, PREFIX_LEN + *cursor_index as Coord);
+            }
+            Some(InputMode::Create(CreateTools {kind, cursor_index, ..})) => {
+                let prefix_len = chars_amount(&creation_prompt(kind)) as Coord;
+                self.window.mv(self.display_settings.height - 1, prefix_len + *cursor_index as Coord);
+            }
             _ => {},
         }
     }
 
-    pub fn draw_current_permission(&self, cs: &mut ColorSystem,
-            bar: &mut Bar, current_permissions: &Option<String>) {
-        if let Some(permissions) = current_permissions {
-            cs.set_paint(&self.window, Paint::with_fg_bg(Color::LightBlue, Color::Default));
-            bar.draw_left(&self.window, permissions, 2);
-        }
-    }
-
-    pub fn draw_current_size(&self, cs: &mut ColorSystem, bar: &mut Bar, size: Option<u64>) {
-        if let Some(size) = size {
-            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Blue, Color::Default));
-            bar.draw_left(&self.window, &human_size(size), 2);
+    pub fn draw_status_fields(&self, cs: &mut ColorSystem, bar: &mut Bar,
+            details: &Option<Details>, fields: &[StatusField]) {
+        if let Some(details) = details {
+            let now = now_seconds();
+            for field in fields.iter() {
+                let color = match field {
+                    StatusField::Permissions                   => Color::LightBlue,
+                    StatusField::Size                          => Color::Blue,
+                    StatusField::Owner | StatusField::Group    => Color::Yellow,
+                    StatusField::Time(..)                      => Color::Green,
+                    StatusField::Links | StatusField::Inode |
+                        StatusField::Device                    => Color::Purple,
+                };
+                cs.set_paint(&self.window, Paint::with_fg_bg(color, Color::Default));
+                bar.draw_left(&self.window, &status_field_text(details, *field, now), 2);
+            }
         }
     }
 
@@ -315,6 +429,12 @@ impl Renderer {
         bar.draw_left(&self.window, &text, 2);
     }
 
+    pub fn draw_sorting(&self, cs: &mut ColorSystem, bar: &mut Bar, sorting: &Sorting) {
+        let text = format!("sort: {}", sorting_description(sorting));
+        cs.set_paint(&self.window, Paint::with_fg_bg(Color::LightBlue, Color::Default));
+        bar.draw_left(&self.window, &text, 2);
+    }
+
     pub fn draw_cumulative_size_text(&self, cs: &mut ColorSystem,
             bar: &mut Bar, cumulative_size_text: &Option<String>) {
         if let Some(text) = cumulative_size_text.as_ref() {
@@ -323,6 +443,14 @@ impl Renderer {
         }
     }
 
+    pub fn maybe_draw_transfers_summary(&self, cs: &mut ColorSystem,
+            bar: &mut Bar, transfers: &[Transfer]) {
+        if let Some(text) = transfers_summary(transfers) {
+            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default));
+            bar.draw_right(&self.window, &text, 2);
+        }
+    }
+
     // returns whether to assign None to notification
     pub fn draw_notification(&mut self, cs: &mut ColorSystem,
             bar: &mut Bar, notification: &Option<Notification>) {
@@ -341,6 +469,27 @@ impl Renderer {
         }
     }
 
+    // At the right end, like vim shows them
+    pub fn maybe_draw_pending_keys(&self, cs: &mut ColorSystem, bar: &mut Bar, text: &str) {
+        if text.is_empty() { return; }
+        cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default).bold());
+        bar.draw_right(&self.window, text, 2);
+    }
+
+    pub fn maybe_draw_visual_range(&self, cs: &mut ColorSystem, bar: &mut Bar, range: Option<(usize, usize)>) {
+        if let Some((first, last)) = range {
+            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default).bold());
+            bar.draw_left(&self.window, &format!("-- VISUAL -- {}", last - first + 1), 2);
+        }
+    }
+
+    pub fn maybe_draw_filter(&self, cs: &mut ColorSystem, bar: &mut Bar, filter: &Option<NameFilter>) {
+        if let Some(filter) = filter {
+            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Yellow, Color::Default).bold());
+            bar.draw_left(&self.window, &format!("[filter: {}]", filter.text), 2);
+        }
+    }
+
     pub fn draw_tabs(&self, cs: &mut ColorSystem, bar: &mut Bar, tabs: &Vec<Tab>, current_index: usize) {
         if tabs.len() == 1 { return; }
         for (index, tab) in tabs.iter().enumerate().rev() {
@@ -393,6 +542,37 @@ impl Renderer {
         }
     }
 
+    // Same look as the available matches: a list of (key, description) rows
+    // above the bottom bar, with the title inside the upper border
+    pub fn draw_list_popup(&self, cs: &mut ColorSystem, title: &str, rows: &[(String, String)]) {
+        let max_rows = self.display_settings.height as usize - 4;
+        let rows_amount = rows.len().min(max_rows);
+
+        // Borders
+        cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default));
+        let y = self.display_settings.height - 2 - rows_amount as Coord - 1;
+        self.window.mv(y, 0);
+        self.window.hline(ACS_HLINE(), self.display_settings.width);
+        self.window.mv(self.display_settings.height - 2, 0);
+        self.window.hline(ACS_HLINE(), self.display_settings.width);
+        cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+        mvprintw(&self.window, y, 1, title);
+
+        let key_width = rows.iter().map(|(key, _)| chars_amount(key)).max().unwrap_or(0) as Coord + 2;
+        for (i, (key, description)) in rows.iter().take(rows_amount).enumerate() {
+            let y = y + 1 + i as Coord;
+            self.window.mv(y, 0);
+            self.window.hline(' ', self.display_settings.width);
+
+            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default).bold());
+            mvprintw(&self.window, y, 0, key);
+            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Green, Color::Default));
+            let description = maybe_truncate(description,
+                (self.display_settings.width - key_width).max(0) as usize);
+            mvprintw(&self.window, y, key_width, &description);
+        }
+    }
+
     fn draw_empty_sign(&self, cs: &mut ColorSystem, column_index: usize) {
         let (begin, _) = self.display_settings.columns_coord[column_index];
         const EMPTY_TEXT: &str = "empty";
@@ -411,13 +591,14 @@ impl Renderer {
     }
 
     pub fn list_entry(&self, cs: &mut ColorSystem, column_index: usize,
-            y: usize, entry: &DirEntry, under_cursor: bool, selected: bool) {
+            y: usize, entry: &DirEntry, under_cursor: bool, pending: bool) {
         let paint = maybe_selected_paint_from(entry.paint, under_cursor);
 
         let y = y as Coord + self.display_settings.entries_display_begin;
         let (mut begin, end) = self.display_settings.columns_coord[column_index];
-        if selected {
-            cs.set_paint(&self.window, Paint::with_fg_bg(Color::Red, Color::Default));
+        if entry.is_selected || pending {
+            let color = if pending { Color::Yellow } else { Color::Red };
+            cs.set_paint(&self.window, Paint::with_fg_bg(color, Color::Default));
             self.window.mvaddch(y, begin + 1, ACS_CKBOARD());
             self.window.mvaddch(y, begin + 2, ' ');
             begin += 2;
@@ -434,26 +615,44 @@ impl Renderer {
             let name_len = chars_amount(&name) as Coord;
             let leftover = column_width - name_len;
             mvprintw(&self.window, y, begin + 1, &name);
+            let kept = kept_before_delimiter(&entry.name, column_width);
+            self.highlight_chars(cs, y, begin + 1, entry, kept, paint);
             self.window.mv(y, begin + 1 + name_len);
             self.window.hline(' ', leftover);
         } else { // everything fits OK
             mvprintw(&self.window, y, begin + 1, &entry.name);
+            self.highlight_chars(cs, y, begin + 1, entry, name_len as usize, paint);
             self.window.mv(y, begin + 1 + name_len);
             self.window.hline(' ', empty_space_length);
             mvprintw(&self.window, y, begin + 1 + name_len + empty_space_length, &size);
         }
     }
 
-    fn list_entries(&self, mut cs: &mut ColorSystem, column_index: usize,
-            entries: &Vec<DirEntry>, cursor_index: Option<usize>, shift: usize) {
-        for (index, entry) in entries.into_iter().enumerate()
+    // Redraws the chars matched by the search over the name, underlined.
+    // Only the kept ones are shown when the name is truncated.
+    fn highlight_chars(&self, cs: &mut ColorSystem, y: Coord, x: Coord,
+            entry: &DirEntry, kept: usize, paint: Paint) {
+        if entry.highlighted.is_empty() { return; }
+        cs.set_paint(&self.window, Paint {underlined: true, ..paint}.bold());
+        for (position, c) in entry.name.chars().enumerate().take(kept) {
+            if entry.highlighted.contains(&position) {
+                mvprintw(&self.window, y, x + position as Coord, &c.to_string());
+            }
+        }
+        cs.set_paint(&self.window, paint);
+    }
+
+    fn list_entries(&self, cs: &mut ColorSystem, column_index: usize,
+            entries: &[DirEntry], cursor_index: Option<usize>, shift: usize,
+            pending: Option<(usize, usize)>) {
+        for (index, entry) in entries.iter().enumerate()
                 .skip(shift).take(self.display_settings.column_effective_height) {
             let under_cursor = match cursor_index {
                 Some(i) => (i == index),
                 None    => false,
             };
-            self.list_entry(&mut cs, column_index, index - shift,
-                            &entry, under_cursor, entry.is_selected);
+            let in_pending = pending.is_some_and(|(first, last)| first <= index && index <= last);
+            self.list_entry(cs, column_index, index - shift, entry, under_cursor, in_pending);
         }
     }
 }
@@ -1,6 +1,6 @@
 // use std::path::Path;
 use std::path::PathBuf;
-use std::fs::{self, DirEntry, Metadata};
+use std::fs::{self, DirEntry, Metadata, FileType};
 use std::io::BufReader;
 use std::io::BufRead;
 use std::ffi::OsStr;
@@ -10,7 +10,7 @@ use std::ffi::OsStr;
 use std::time::{UNIX_EPOCH};
 use std::io::{Read};
 use std::fs::File;
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{PermissionsExt, FileTypeExt, MetadataExt};
 
 // use std::fs::OpenOptions;
 // use std::io::{Write};
@@ -27,19 +27,17 @@ pub struct Permissions {
     pub owner: u32,
     pub group: u32,
     pub world: u32,
-    pub is_directory: bool,
-    pub is_symlink: bool,
+    pub special: u32, // setuid 4, setgid 2, sticky 1
+    pub type_letter: char,
 }
 
 impl Permissions {
+    // The same as `ls -l`, e.g. "drwxr-sr-t" or "crw-rw----"
     pub fn string_representation(&self) -> String {
-        let dir = (if      self.is_directory {"d"}
-                   else if self.is_symlink   {"l"}
-                   else                      {"-"}).to_string();
-        let owner = permission_number_to_string_representation(self.owner);
-        let group = permission_number_to_string_representation(self.group);
-        let world = permission_number_to_string_representation(self.world);
-        dir + &owner + &group + &world
+        let owner = permission_number_to_string_representation(self.owner, self.special & 4 != 0, 's');
+        let group = permission_number_to_string_representation(self.group, self.special & 2 != 0, 's');
+        let world = permission_number_to_string_representation(self.world, self.special & 1 != 0, 't');
+        self.type_letter.to_string() + &owner + &group + &world
     }
 
     fn empty() -> Permissions {
@@ -47,13 +45,14 @@ impl Permissions {
             owner: 0,
             group: 0,
             world: 0,
-            is_directory: false,
-            is_symlink: false,
+            special: 0,
+            type_letter: '-',
         }
     }
 }
 
-fn permission_number_to_string_representation(mut n: u32) -> String {
+// The special bit takes the place of `x`: lowercase if executable as well, uppercase otherwise
+pub fn permission_number_to_string_representation(mut n: u32, special: bool, letter: char) -> String {
     let mut s = String::new();
     if n >= 4 {
         s.push('r');
@@ -64,28 +63,26 @@ fn permission_number_to_string_representation(mut n: u32) -> String {
         n -= 2;
     } else { s.push('-'); }
     if n >= 1 {
-        s.push('x');
+        s.push(if special { letter } else { 'x' });
         n -= 1;
-    } else { s.push('-'); }
+    } else { s.push(if special { letter.to_ascii_uppercase() } else { '-' }); }
     assert!(n == 0);
 
     s
 }
 
-fn permissions_from_metadata(metadata: Metadata) -> Permissions {
-    let is_file = metadata.is_file();
-    let is_directory = metadata.is_dir();
-    let is_symlink = !(is_file || is_directory);
+pub fn permissions_from_metadata(metadata: &Metadata) -> Permissions {
     let field = metadata.permissions().mode();
     let world = field % 8;
     let group = (field / 8) % 8;
     let owner = (field / (8*8)) % 8;
+    let special = (field / (8*8*8)) % 8;
     Permissions {
         owner,
         group,
         world,
-        is_directory,
-        is_symlink,
+        special,
+        type_letter: type_letter_of(&entry_type_from(metadata.file_type())),
     }
 }
 
@@ -116,15 +113,45 @@ pub enum EntryType {
     Regular,
     Directory,
     Symlink,
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
     Unknown,
 }
 
+pub fn entry_type_from(file_type: FileType) -> EntryType {
+    if      file_type.is_file()         { EntryType::Regular }
+    else if file_type.is_dir()          { EntryType::Directory }
+    else if file_type.is_symlink()      { EntryType::Symlink }
+    else if file_type.is_block_device() { EntryType::BlockDevice }
+    else if file_type.is_char_device()  { EntryType::CharDevice }
+    else if file_type.is_fifo()         { EntryType::Fifo }
+    else if file_type.is_socket()       { EntryType::Socket }
+    else                                { EntryType::Unknown }
+}
+
+pub fn type_letter_of(entrytype: &EntryType) -> char {
+    match entrytype {
+        EntryType::Regular     => '-',
+        EntryType::Directory   => 'd',
+        EntryType::Symlink     => 'l',
+        EntryType::BlockDevice => 'b',
+        EntryType::CharDevice  => 'c',
+        EntryType::Fifo        => 'p',
+        EntryType::Socket      => 's',
+        EntryType::Unknown     => '?',
+    }
+}
+
 #[derive(Clone)]
 pub struct Entry {
     pub entrytype: EntryType,
     pub name: String,
     pub size: u64,
     pub time_modified: u64,
+    pub time_changed: u64,
+    pub time_accessed: u64,
     pub permissions: Permissions,
 }
 
@@ -244,6 +271,8 @@ pub fn collect_siblings_of(path: &PathBuf, include_hidden: bool) -> Vec<Entry> {
             name: "/".to_string(),
             size: 4096,
             time_modified: 0,
+            time_changed: 0,
+            time_accessed: 0,
             permissions: Permissions::empty(),
         }]
     } else {
@@ -257,24 +286,21 @@ pub fn collect_siblings_of(path: &PathBuf, include_hidden: bool) -> Vec<Entry> {
 fn into_entry(dir_entry: DirEntry) -> Entry {
     let name = dir_entry.file_name().to_str().unwrap().to_string();
     let meta = dir_entry.metadata().expect(&format!("Could not read metadata for {}", name));
-    let size = meta.len();
+    entry_from_metadata(name, &meta)
+}
+
+// The metadata of the entry itself, not of what a symlink points to
+pub fn entry_from_metadata(name: String, meta: &Metadata) -> Entry {
     let time_modified = meta.modified().expect("Could not read modify time")
                             .duration_since(UNIX_EPOCH).unwrap().as_secs();
-    let permissions = permissions_from_metadata(meta);
-    let entrytype = {
-        let ft = dir_entry.file_type().expect("Could not retrieve filetype");
-        if ft.is_file()         { EntryType::Regular }
-        else if ft.is_dir()     { EntryType::Directory }
-        else if ft.is_symlink() { EntryType::Symlink }
-        else                    { EntryType::Unknown }
-    };
-
     Entry {
-        entrytype,
+        entrytype: entry_type_from(meta.file_type()),
         name,
-        size,
+        size: meta.len(),
         time_modified,
-        permissions,
+        time_changed: meta.ctime().max(0) as u64,
+        time_accessed: meta.atime().max(0) as u64,
+        permissions: permissions_from_metadata(meta),
     }
 }
 
@@ -286,12 +312,6 @@ pub fn maybe_size(path: &PathBuf) -> Option<Size> {
     Some(meta.unwrap().len())
 }
 
-fn size(path: &PathBuf) -> Size {
-    path.symlink_metadata() // does not follow symlinks
-        .expect(&format!("Could not read metadata for {:?}", path))
-        .len()
-}
-
 pub fn human_size(mut size: u64) -> String {
     if size < 1024 { return size.to_string() + " B"; }
 
@@ -317,19 +337,52 @@ pub fn human_size(mut size: u64) -> String {
 }
 
 
+// Whatever can not be read counts as nothing
 pub fn cumulative_size(path: &PathBuf) -> Size {
-    let meta = path.symlink_metadata();
-    if meta.is_err() { return 0; }
-    if meta.unwrap().is_dir() { // does not follow symlinks
-        fs::read_dir(path).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .map(|path| cumulative_size(&path))
-            .sum()
-    } else { size(path) }
+    let meta = match path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(_)   => return 0,
+    };
+    if meta.is_dir() { // does not follow symlinks
+        match fs::read_dir(path) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok())
+                .map(|entry| cumulative_size(&entry.path()))
+                .sum(),
+            Err(_) => 0,
+        }
+    } else { meta.len() }
+}
+
+// Expands the leading `~` and every `$VAR` or `${VAR}`, `$$` is a `$`.
+// Unknown variables expand to nothing.
+pub fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut chars = path.chars().peekable();
+    if path == "~" || path.starts_with("~/") {
+        chars.next();
+        expanded += &std::env::var("HOME").unwrap_or_default();
+    }
+    while let Some(c) = chars.next() {
+        if c != '$' { expanded.push(c); continue; }
+        if chars.peek() == Some(&'$') { chars.next(); expanded.push('$'); continue; }
+        let braced = chars.peek() == Some(&'{');
+        if braced { chars.next(); }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if braced && c == '}' { chars.next(); break; }
+            if !braced && !c.is_alphanumeric() && c != '_' { break; }
+            name.push(c);
+            chars.next();
+        }
+        if name.is_empty() && !braced { expanded.push('$'); }
+        else { expanded += &std::env::var(&name).unwrap_or_default(); }
+    }
+    PathBuf::from(expanded)
 }
 
-pub fn absolute_pathbuf() -> PathBuf {
-    std::env::current_exe().expect("Cannot get absolute PathBuf")
+// So that `expand_path` gives the path back as it is
+pub fn escape_for_expansion(path: &str) -> String {
+    path.replace('$', "$$")
 }
 
 pub fn is_root(path: &PathBuf) -> bool {
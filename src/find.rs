@@ -0,0 +1,108 @@
+use crate::filesystem::*;
+use crate::utils::*;
+
+use std::path::{Path, PathBuf};
+use std::fs::{self, Metadata};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+
+// Walks the tree below the root on a worker thread, breadth first so that the
+// shallow matches come first. The found entries are named by their paths
+// relative to the root. Symlinks to directories are not followed.
+//-----------------------------------------------------------------------------
+pub struct Find {
+    pub unreadable: usize, // directories that could not be listed
+    pub finished: bool,
+    cancelled: Arc<AtomicBool>,
+    events: Receiver<FindEvent>,
+}
+
+enum FindEvent {
+    Found(Entry),
+    Finished(usize), // with the amount of unreadable directories
+}
+
+impl Find {
+    pub fn start(root: PathBuf, query: &str, include_hidden: bool, ignored: Vec<String>) -> Find {
+        let (sender, events) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        let query = query.to_string();
+        thread::spawn(move || {
+            let unreadable = walk(&root, include_hidden, &ignored, &worker_cancelled,
+                |relative_path, metadata| {
+                    let name = relative_path.file_name().unwrap().to_string_lossy();
+                    if !contains_pattern(&name, &query) { return true; }
+                    let found = entry_from_metadata(relative_path.to_string_lossy().into_owned(), metadata);
+                    sender.send(FindEvent::Found(found)).is_ok()
+                });
+            let _ = sender.send(FindEvent::Finished(unreadable)); // the UI may have gone already
+        });
+
+        Find {
+            unreadable: 0,
+            finished: false,
+            cancelled,
+            events,
+        }
+    }
+
+    // Returns what the worker has found since the last call
+    pub fn poll(&mut self) -> Vec<Entry> {
+        let mut found = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(FindEvent::Found(entry))         => found.push(entry),
+                Ok(FindEvent::Finished(unreadable)) => {
+                    self.unreadable = unreadable;
+                    self.finished = true;
+                },
+                Err(TryRecvError::Empty)            => break,
+                Err(TryRecvError::Disconnected)     => { self.finished = true; break; },
+            }
+        }
+        found
+    }
+}
+
+// The worker checks before every entry, so it stops right away
+impl Drop for Find {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Calls `visit` with the path relative to the root of every entry, until it
+// returns false. Returns the amount of directories that could not be read.
+pub fn walk<F>(root: &Path, include_hidden: bool, ignored: &[String],
+               cancelled: &AtomicBool, mut visit: F) -> usize
+        where F: FnMut(&PathBuf, &Metadata) -> bool {
+    let mut unreadable = 0;
+    let mut dirs = VecDeque::new();
+    dirs.push_back(PathBuf::new()); // relative to the root
+    while let Some(relative_dir) = dirs.pop_front() {
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(root.join(&relative_dir)) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).collect(),
+            Err(_) => { unreadable += 1; continue; },
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries.into_iter() {
+            if cancelled.load(Ordering::Relaxed) { return unreadable; }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !include_hidden && name.starts_with('.') { continue; }
+            if ignored.contains(&name) { continue; }
+            let metadata = match entry.metadata() { // does not follow symlinks
+                Ok(metadata) => metadata,
+                Err(_)       => continue,
+            };
+            let relative_path = relative_dir.join(&name);
+            if !visit(&relative_path, &metadata) { return unreadable; }
+            if metadata.is_dir() { dirs.push_back(relative_path); }
+        }
+    }
+    unreadable
+}
@@ -0,0 +1,246 @@
+use crate::find::walk;
+use crate::utils::*;
+
+use regex::{Regex, RegexBuilder};
+use std::path::PathBuf;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+
+// Searches the contents of the files below the root on a worker thread, in the
+// order of the find. A query between slashes is a regex (`/fn \w+/`, or `/.../i`
+// to ignore the case), anything else is a literal matched like the search does it.
+//-----------------------------------------------------------------------------
+#[derive(Clone)]
+pub struct GrepMatch {
+    pub path: PathBuf, // relative to the root
+    pub line: usize,   // counting from 1
+    pub text: String,
+}
+
+pub enum GrepPattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+pub struct Grep {
+    pub unreadable: usize, // directories and files
+    pub truncated: bool,   // stopped at MAX_MATCHES
+    pub finished: bool,
+    cancelled: Arc<AtomicBool>,
+    events: Receiver<GrepEvent>,
+}
+
+enum GrepEvent {
+    Found(GrepMatch),
+    Finished(usize, bool), // unreadable, truncated
+}
+
+const MAX_MATCHES: usize = 10000;
+const MAX_TEXT_LEN: usize = 300;
+const BINARY_CHECK_LEN: usize = 8000; // the way git and grep tell binaries apart
+
+pub fn parse_grep_query(query: &str) -> Result<GrepPattern, String> {
+    if query.is_empty() { return Err("Empty query".to_string()); }
+    let ignore_case = query.len() > 2 && query.starts_with('/') && query.ends_with("/i");
+    if !ignore_case && (query.len() < 2 || !query.starts_with('/') || !query.ends_with('/')) {
+        return Ok(GrepPattern::Literal(query.to_string()));
+    }
+    let end = if ignore_case { query.len() - 2 } else { query.len() - 1 };
+    RegexBuilder::new(&query[1..end]).case_insensitive(ignore_case).build()
+        .map(GrepPattern::Regex)
+        .map_err(|e| format!("Invalid regex: {}", e.to_string().lines().last().unwrap_or("")))
+}
+
+fn is_match(pattern: &GrepPattern, line: &str) -> bool {
+    match pattern {
+        GrepPattern::Literal(text) => contains_pattern(line, text),
+        GrepPattern::Regex(regex)  => regex.is_match(line),
+    }
+}
+
+// None for a binary file, which has a NUL among its first bytes.
+// Only those are read here, the rest is streamed line by line.
+fn open_text(path: &PathBuf) -> io::Result<Option<impl BufRead>> {
+    let mut file = File::open(path)?;
+    let mut head = Vec::with_capacity(BINARY_CHECK_LEN);
+    (&mut file).take(BINARY_CHECK_LEN as u64).read_to_end(&mut head)?;
+    if head.contains(&0) { return Ok(None); }
+    Ok(Some(BufReader::new(io::Cursor::new(head).chain(file))))
+}
+
+// Like `lines()`, but what is not UTF-8 is replaced instead of failing
+fn lines_of<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<String>> {
+    reader.split(b'\n').map(|line| line.map(|mut bytes| {
+        if bytes.last() == Some(&b'\r') { bytes.pop(); }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }))
+}
+
+impl Grep {
+    pub fn start(root: PathBuf, pattern: GrepPattern, include_hidden: bool, ignored: Vec<String>) -> Grep {
+        let (sender, events) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            let mut unreadable_files = 0;
+            let mut count = 0;
+            let unreadable_dirs = walk(&root, include_hidden, &ignored, &worker_cancelled,
+                |relative_path, metadata| {
+                    if !metadata.is_file() { return true; }
+                    let reader = match open_text(&root.join(relative_path)) {
+                        Ok(Some(reader)) => reader,
+                        Ok(None)         => return true, // binary
+                        Err(_)           => { unreadable_files += 1; return true; },
+                    };
+                    for (index, line) in lines_of(reader).enumerate() {
+                        if worker_cancelled.load(Ordering::Relaxed) { return false; }
+                        let line = match line {
+                            Ok(line) => line,
+                            Err(_)   => { unreadable_files += 1; return true; },
+                        };
+                        if !is_match(&pattern, &line) { continue; }
+                        let found = GrepMatch {
+                            path: relative_path.clone(),
+                            line: index + 1,
+                            text: maybe_truncate(line.trim(), MAX_TEXT_LEN),
+                        };
+                        if sender.send(GrepEvent::Found(found)).is_err() { return false; }
+                        count += 1;
+                        if count == MAX_MATCHES { return false; }
+                    }
+                    true
+                });
+            // The UI may have gone already
+            let _ = sender.send(GrepEvent::Finished(unreadable_dirs + unreadable_files, count == MAX_MATCHES));
+        });
+
+        Grep {
+            unreadable: 0,
+            truncated: false,
+            finished: false,
+            cancelled,
+            events,
+        }
+    }
+
+    // Returns what the worker has found since the last call
+    pub fn poll(&mut self) -> Vec<GrepMatch> {
+        let mut found = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(GrepEvent::Found(found_match))              => found.push(found_match),
+                Ok(GrepEvent::Finished(unreadable, truncated)) => {
+                    self.unreadable = unreadable;
+                    self.truncated = truncated;
+                    self.finished = true;
+                },
+                Err(TryRecvError::Empty)                       => break,
+                Err(TryRecvError::Disconnected)                => { self.finished = true; break; },
+            }
+        }
+        found
+    }
+}
+
+// The worker checks before every entry and line, so it stops right away
+impl Drop for Grep {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// E.g. "src/main.rs:12: fn main() {"
+pub fn grep_line_of(found: &GrepMatch) -> String {
+    format!("{}:{}: {}", found.path.to_string_lossy(), found.line, found.text)
+}
+
+// The lines around the match, numbered, the matching one marked with '>'
+pub fn preview_around(path: &PathBuf, line: usize, height: usize, width: usize) -> Vec<String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_)   => return Vec::new(),
+    };
+    let first = line.saturating_sub(height / 3).max(1);
+    // Reads no further than the last shown line
+    lines_of(BufReader::new(file)).map_while(Result::ok).enumerate()
+        .skip(first - 1).take(height)
+        .map(|(index, text)| {
+            let marker = if index + 1 == line { '>' } else { ' ' };
+            maybe_truncate(&format!("{}{:>5} {}", marker, index + 1, text.trim_end()), width)
+        }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A fresh directory for one test, the tests run in parallel
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vffm-grep-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn lines_in(path: &PathBuf) -> Vec<String> {
+        match open_text(path).unwrap() {
+            Some(reader) => lines_of(reader).map(Result::unwrap).collect(),
+            None         => panic!("{} should not be binary", path.display()),
+        }
+    }
+
+    #[test]
+    fn open_text_skips_the_binaries() {
+        let dir = test_dir("binaries");
+        let binary = dir.join("binary");
+        fs::write(&binary, b"text\n\0more").unwrap();
+        assert!(open_text(&binary).unwrap().is_none());
+        let late_nul = dir.join("late_nul");
+        let mut bytes = vec![b'a'; BINARY_CHECK_LEN];
+        bytes.extend_from_slice(b"\0\n");
+        fs::write(&late_nul, &bytes).unwrap();
+        assert!(open_text(&late_nul).unwrap().is_some());
+        assert!(open_text(&dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lines_continue_past_the_checked_bytes() {
+        let dir = test_dir("long");
+        let path = dir.join("long");
+        let long_line = "x".repeat(BINARY_CHECK_LEN - 2);
+        fs::write(&path, format!("{}\nsecond\nthird", long_line)).unwrap();
+        assert_eq!(lines_in(&path), vec![long_line, "second".to_string(), "third".to_string()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lines_drop_the_carriage_returns_and_replace_what_is_not_utf8() {
+        let dir = test_dir("lines");
+        let path = dir.join("lines");
+        fs::write(&path, b"one\r\nt\xffo\n\nthree\n").unwrap();
+        assert_eq!(lines_in(&path), vec!["one", "t\u{fffd}o", "", "three"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn preview_shows_the_lines_around_the_match() {
+        let dir = test_dir("preview");
+        let path = dir.join("text");
+        let text: Vec<String> = (1..=100).map(|n| format!("line {}", n)).collect();
+        fs::write(&path, text.join("\n")).unwrap();
+        assert_eq!(preview_around(&path, 10, 4, 80), vec![
+            "     9 line 9", ">   10 line 10", "    11 line 11", "    12 line 12",
+        ]);
+        assert_eq!(preview_around(&path, 1, 2, 80), vec![">    1 line 1", "     2 line 2"]);
+        assert_eq!(preview_around(&path, 100, 3, 80), vec!["    99 line 99", ">  100 line 100"]);
+        assert!(preview_around(&dir.join("missing"), 1, 3, 80).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
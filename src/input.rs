@@ -15,10 +15,16 @@ pub enum Input {
     Delete,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SortingType {
     Lexicographically,
+    CaseInsensitive,
+    Natural, // "file2" before "file10"
+    Extension,
+    Size,
     TimeModified,
+    TimeChanged,
+    TimeAccessed,
     Any,
 }
 
@@ -33,15 +39,17 @@ fn regular(chars: &str) -> Combination {
     Combination::Str(chars.to_string())
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum Command {
     // Terminate,
-    GoTo(&'static str),
+    GoTo(String),
     Up(u32),
     Down(u32),
     Left,
     Right,
     Sort(SortingType),
+    ToggleSortReverse,
+    ToggleDirectoriesFirst,
     Remove,
     Cut,
     Update,
@@ -51,16 +59,40 @@ pub enum Command {
     SelectUnderCursor,
     InvertSelection,
     ClearSelection,
+    SelectMatching,
+    DeselectMatching,
+    EnterVisualMode,
     NewTab,
     CloseTab,
     NextTab,
     PreviousTab,
     EnterSearchMode,
+    EnterFindMode,
+    EnterGrepMode,
     ChangeCurrentName,
     EnterCommand,
     Home,
     End,
     ToggleHidden,
+    EditFilter,
+    ClearFilter,
+    GoToBookmark,
+    AddBookmark,
+    RemoveBookmark,
+    ShowTransfers,
+    Delete,
+    ShowTrash,
+    Restore,
+    Undo,
+    Redo,
+    BulkRename,
+    PatternRename,
+    CreateFile,
+    CreateDirectory,
+    CreateSymlink(bool), // relative
+    CreateHardLink,
+    EditPermissions,
+    ShowDetails,
 }
 
 
@@ -68,22 +100,12 @@ pub const fn max_combination_len() -> usize { 5 }
 
 pub type Match   = (Combination, Command);
 pub type Matches = HashMap<Combination, Vec<Match>>;
+pub type Binding = (Combination, Command);
 
-pub fn generate_possible_inputs() -> Matches {
-    let mut inputs: Matches = HashMap::new();
+pub fn default_bindings() -> Vec<Binding> {
+    let mut bindings: Vec<Binding> = Vec::new();
     let mut insert = |combination: Combination, command: Command| {
-        let comb = combination.clone();
-        if let Combination::Str(chars) = comb {
-            for i in 1..=chars.len() {
-                let mut partial = chars.clone();
-                partial.truncate(i);
-                let v = inputs.entry(Combination::Str(partial)).or_insert(Vec::new());
-                (*v).push((combination.clone(), command.clone()));
-            }
-        } else {
-            let v = inputs.entry(comb).or_insert(Vec::new());
-            (*v).push((combination.clone(), command.clone()));
-        }
+        bindings.push((combination, command));
     };
     insert(regular("h"),  Command::Left);
     // insert(regular("р"),  Command::Left);
@@ -98,36 +120,75 @@ pub fn generate_possible_inputs() -> Matches {
     insert(regular("sl"), Command::Sort(SortingType::Lexicographically));
     insert(regular("st"), Command::Sort(SortingType::TimeModified));
     insert(regular("sa"), Command::Sort(SortingType::Any));
-    insert(regular("gh"), Command::GoTo("/home/igorek/"));
-    insert(regular("gd"), Command::GoTo("/home/igorek/Downloads"));
-    insert(regular("gD"), Command::GoTo("/home/igorek/Stuff/dev"));
-    insert(regular("gs"), Command::GoTo("/home/igorek/Storage/Studying"));
-    insert(regular("gS"), Command::GoTo("/home/igorek/Storage"));
-    insert(regular("gT"), Command::GoTo("/home/igorek/Storage/torrents"));
-    insert(regular("gc"), Command::GoTo("/home/igorek/screenshots"));
-    insert(regular("gt"), Command::GoTo("/home/igorek/Stuff"));
-    insert(regular("gm"), Command::GoTo("/home/igorek/Mutual"));
-    insert(regular("ge"), Command::GoTo("/mnt/External"));
-    insert(regular("gE"), Command::GoTo("/mnt/External2"));
+    insert(regular("si"), Command::Sort(SortingType::CaseInsensitive));
+    insert(regular("sn"), Command::Sort(SortingType::Natural));
+    insert(regular("se"), Command::Sort(SortingType::Extension));
+    insert(regular("ss"), Command::Sort(SortingType::Size));
+    insert(regular("sc"), Command::Sort(SortingType::TimeChanged));
+    insert(regular("sA"), Command::Sort(SortingType::TimeAccessed));
+    insert(regular("sr"), Command::ToggleSortReverse);
+    insert(regular("sd"), Command::ToggleDirectoriesFirst);
+    insert(regular("gh"), Command::GoTo("~".to_string()));
+    insert(regular("'"),  Command::GoToBookmark);
+    insert(regular("m"),  Command::AddBookmark);
+    insert(regular("dm"), Command::RemoveBookmark);
     insert(regular("dd"), Command::Remove);
+    insert(regular("dD"), Command::Delete);
     insert(regular("dc"), Command::Cut);
     insert(regular("yy"), Command::Yank);
     insert(regular("pp"), Command::Paste);
-    insert(regular("u"),  Command::Update);
+    insert(regular("u"),  Command::Undo);
+    insert(regular("\x12"), Command::Redo);   // Ctrl-R
+    insert(regular("\x0c"), Command::Update); // Ctrl-L
     insert(regular("cs"), Command::CumulativeSize);
     insert(regular("v"),  Command::SelectUnderCursor);
     insert(regular("V"),  Command::InvertSelection);
     insert(regular("cc"), Command::ClearSelection);
+    insert(regular("+"),  Command::SelectMatching);
+    insert(regular("-"),  Command::DeselectMatching);
+    insert(regular("zv"), Command::EnterVisualMode);
     insert(Combination::Tab,      Command::NextTab);
     insert(Combination::ShiftTab, Command::PreviousTab);
     insert(regular("q"),          Command::CloseTab);
     insert(regular("t"),          Command::NewTab);
     insert(regular("/"),          Command::EnterSearchMode);
+    insert(regular("F"),          Command::EnterFindMode);
+    insert(regular("g/"),         Command::EnterGrepMode);
     insert(regular("cw"),         Command::ChangeCurrentName);
+    insert(regular("cW"),         Command::BulkRename);
+    insert(regular("cr"),         Command::PatternRename);
+    insert(regular("nf"),         Command::CreateFile);
+    insert(regular("nd"),         Command::CreateDirectory);
+    insert(regular("nl"),         Command::CreateSymlink(false));
+    insert(regular("nL"),         Command::CreateSymlink(true));
+    insert(regular("nh"),         Command::CreateHardLink);
+    insert(regular("cp"),         Command::EditPermissions);
+    insert(regular("i"),          Command::ShowDetails);
     insert(regular("!"),          Command::EnterCommand);
     insert(regular("gg"),         Command::Home);
     insert(regular("G"),          Command::End);
     insert(regular("zh"),         Command::ToggleHidden);
+    insert(regular("zf"),         Command::EditFilter);
+    insert(regular("X"),          Command::ClearFilter);
+    insert(regular("T"),          Command::ShowTransfers);
+    insert(regular("gT"),         Command::ShowTrash);
+    insert(regular("R"),          Command::Restore);
+    bindings
+}
+
+// Every prefix of a combination gets the list of all bindings it may lead to
+pub fn generate_possible_inputs(bindings: &[Binding]) -> Matches {
+    let mut inputs: Matches = HashMap::new();
+    for (combination, command) in bindings.iter() {
+        if let Combination::Str(chars) = combination {
+            for (i, _) in chars.char_indices().skip(1) {
+                let v = inputs.entry(regular(&chars[..i])).or_default();
+                (*v).push((combination.clone(), command.clone()));
+            }
+        }
+        let v = inputs.entry(combination.clone()).or_default();
+        (*v).push((combination.clone(), command.clone()));
+    }
     inputs
 }
 
@@ -140,7 +201,10 @@ pub fn description_of(command: &Command) -> String {
         Command::Left => "Navigate to the parent directory".to_string(),
         Command::Right => "Navigate into the child directory or file".to_string(),
         Command::Sort(sorting_type) => format!("Sort entries {:?}", sorting_type),
-        Command::Remove => "Remove selected entry(ies) from the filesystem".to_string(),
+        Command::ToggleSortReverse => "Reverse the sorting order".to_string(),
+        Command::ToggleDirectoriesFirst => "Toggle listing directories before files".to_string(),
+        Command::Remove => "Move selected entry(ies) to the trash".to_string(),
+        Command::Delete => "Delete selected entry(ies) permanently".to_string(),
         Command::Update => "Update the current directory".to_string(),
         Command::Yank => "Yank selected entries into buffer".to_string(),
         Command::Cut => "Cut selected entries into buffer".to_string(),
@@ -149,19 +213,48 @@ pub fn description_of(command: &Command) -> String {
         Command::SelectUnderCursor => "Flips the selection for the entry under cursor".to_string(),
         Command::InvertSelection => "Inverts the selection in the current directory".to_string(),
         Command::ClearSelection => "Clears the list of selected items and the buffer of yanked or cut items".to_string(),
+        Command::SelectMatching => "Select the entries that match a query, e.g. `*.log size>10M age>1w`".to_string(),
+        Command::DeselectMatching => "Deselect the entries that match a query".to_string(),
+        Command::EnterVisualMode => "Select a range: move, then `v` or Enter selects it, `x` deselects it".to_string(),
         Command::NewTab => "Creates a new tab that is a clone of the current one".to_string(),
         Command::CloseTab => "Closes current Tab. If it is the last tab then closes the program".to_string(),
         Command::NextTab => "Selects the next Tab (if any) as the new current tab".to_string(),
         Command::PreviousTab => "Selects the previous Tab (if any) as the new current tab".to_string(),
         Command::EnterSearchMode => "Go inside the search bar to edit the query".to_string(),
+        Command::EnterFindMode => "Find entries in the whole tree below the current directory".to_string(),
+        Command::EnterGrepMode => "Search the contents of the files below the current directory".to_string(),
         Command::ChangeCurrentName => "Change the name of the current entry".to_string(),
         Command::EnterCommand => "Execute an external command".to_string(),
         Command::Home => "Go to the beginning of the current directory".to_string(),
         Command::End => "Go to the end of the current directory".to_string(),
         Command::ToggleHidden => "Toggle showing hidden files".to_string(),
+        Command::EditFilter => "Show only the names that match a glob or a /regex/ in this tab".to_string(),
+        Command::ClearFilter => "Show all the names again".to_string(),
+        Command::GoToBookmark => "Show the bookmarks and go to one of them".to_string(),
+        Command::AddBookmark => "Bookmark the current directory under a key".to_string(),
+        Command::RemoveBookmark => "Remove the bookmark under a key".to_string(),
+        Command::ShowTransfers => "Show or hide the progress of the ongoing transfers".to_string(),
+        Command::ShowTrash => "Go to the trash".to_string(),
+        Command::Restore => "Restore selected entry(ies) from the trash to where they were".to_string(),
+        Command::Undo => "Undo the last rename, move, copy, creation, trash or restore".to_string(),
+        Command::Redo => "Redo what has been undone".to_string(),
+        Command::BulkRename => "Rename the selected entries (or all in the directory) in $EDITOR".to_string(),
+        Command::PatternRename => "Rename the selected entries (or all in the directory) by a pattern".to_string(),
+        Command::CreateFile => "Create an empty file".to_string(),
+        Command::CreateDirectory => "Create a directory along with the missing parents".to_string(),
+        Command::CreateSymlink(false) => "Create symlinks to the selected entry(ies)".to_string(),
+        Command::CreateSymlink(true) => "Create relative symlinks to the selected entry(ies)".to_string(),
+        Command::CreateHardLink => "Create hard links to the selected entry(ies)".to_string(),
+        Command::EditPermissions => "Change the permissions, owner and group of the selected entry(ies)".to_string(),
+        Command::ShowDetails => "Show or hide the details of the current entry".to_string(),
     }
 }
 
+// The commands that the visual mode lets through
+pub fn moves_cursor_only(command: &Command) -> bool {
+    matches!(command, Command::Up(_) | Command::Down(_) | Command::Home | Command::End)
+}
+
 pub fn exact_match(matches: &Vec<Match>, input: &Combination) -> bool {
     (matches.len() == 1) && (matches[0].0 == *input)
 }
@@ -1,10 +1,24 @@
 use crate::direntry::*;
+use crate::create::CreationKind;
+use crate::permissions::PermissionsEditor;
+use crate::grep::GrepMatch;
+use crate::matching::SearchMode;
+
+use std::path::PathBuf;
 
 #[derive(Clone)]
 pub enum InputMode {
     Search(SearchTools),
+    Find(FindTools),
+    Grep(GrepTools),
     ChangeName(ChangeNameTools),
     Command(CommandTools),
+    Confirm(ConfirmTools),
+    PatternRename(PatternRenameTools),
+    Create(CreateTools),
+    Permissions(PermissionsEditor),
+    Filter(FilterTools),
+    Select(SelectTools),
 }
 
 #[derive(Clone)]
@@ -12,6 +26,28 @@ pub struct SearchTools {
     pub query: String,
     pub cursor_index: Option<usize>, // None if not if focus
     pub current_siblings_backup: Vec<DirEntry>,
+    pub mode: SearchMode, // can be switched while typing, keeping the query
+}
+
+// The found entries take the place of the siblings, named by their paths
+// relative to the directory the search has started in
+#[derive(Clone)]
+pub struct FindTools {
+    pub query: String,
+    pub cursor_index: Option<usize>, // None once the search has started
+    pub current_siblings_backup: Vec<DirEntry>,
+    pub searching: bool,
+}
+
+// The matches are shown instead of the siblings, which stay as they are
+#[derive(Clone)]
+pub struct GrepTools {
+    pub query: String,
+    pub cursor_index: Option<usize>, // None once the search has started
+    pub matches: Vec<GrepMatch>,
+    pub index: usize,
+    pub shift: usize,
+    pub searching: bool,
 }
 
 #[derive(Clone)]
@@ -25,3 +61,48 @@ pub struct CommandTools {
     pub text: String,
     pub cursor_index: usize,
 }
+
+// Edits the filter of the tab, starting with the current one
+#[derive(Clone)]
+pub struct FilterTools {
+    pub text: String,
+    pub cursor_index: usize,
+}
+
+// The query of the entries to select, or to deselect
+#[derive(Clone)]
+pub struct SelectTools {
+    pub text: String,
+    pub cursor_index: usize,
+    pub select: bool,
+}
+
+// The entries to rename are determined when the pattern is applied
+#[derive(Clone)]
+pub struct PatternRenameTools {
+    pub pattern: String,
+    pub cursor_index: usize,
+}
+
+#[derive(Clone)]
+pub struct CreateTools {
+    pub kind: CreationKind,
+    pub text: String,
+    pub cursor_index: usize,
+}
+
+#[derive(Clone)]
+pub struct ConfirmTools {
+    pub question: String,
+    pub action: ConfirmedAction,
+}
+
+// What gets done once the user answers yes
+#[derive(Clone)]
+pub enum ConfirmedAction {
+    Trash(Vec<PathBuf>),
+    Delete(Vec<PathBuf>),
+    Paste,
+    Rename(PathBuf, String), // the new name replaces an existing entry
+    BulkRename(Vec<(PathBuf, PathBuf)>),
+}
@@ -0,0 +1,223 @@
+use crate::config::*;
+use crate::filesystem::*;
+use crate::utils::*;
+use crate::trash::*;
+use crate::transfer::move_now;
+
+use std::path::{Path, PathBuf};
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::fs;
+
+
+// Every operation knows how to revert itself. Reverting performs another
+// operation, which is what gets reverted to redo the original one:
+//
+//     Move  a -> b    <->  Move    b -> a
+//     Copy  a -> b     ->  Trash   b -> <trashed>
+//     Create b         ->  Trash   b -> <trashed>
+//     Trash a -> t    <->  Restore t -> a
+#[derive(Clone)]
+pub enum Operation {
+    Move    { from: PathBuf, to: PathBuf }, // renames as well
+    Copy    { from: PathBuf, to: PathBuf },
+    Trash   { from: PathBuf, to: PathBuf },
+    Restore { from: PathBuf, to: PathBuf },
+    Create  { path: PathBuf }, // a file, directory or link
+}
+
+pub type Batch = Vec<Operation>; // what a single command has done
+
+// Stored one batch per line, from the oldest to the newest, e.g.
+//
+//     undo move /tmp/a%20b /tmp/c copy /tmp/d /home/e
+//     redo trash /tmp/f /home/user/.local/share/Trash/files/f
+//     undo create /tmp/g /tmp/g
+pub struct Journal {
+    undo: Vec<Batch>,
+    redo: Vec<Batch>,
+    file: Option<PathBuf>,
+}
+
+const MAX_BATCHES: usize = 100;
+
+impl Journal {
+    pub fn load(file: Option<PathBuf>) -> (Journal, Vec<String>) {
+        let mut journal = Journal { undo: Vec::new(), redo: Vec::new(), file };
+        let mut errors = Vec::new();
+        if let Some(file) = journal.file.as_ref() {
+            match fs::read_to_string(file) {
+                Ok(contents) => for (index, line) in contents.lines().enumerate() {
+                    if line.trim().is_empty() { continue; }
+                    let mut parts = line.split_whitespace();
+                    let stack = match parts.next() {
+                        Some("undo") => &mut journal.undo,
+                        Some("redo") => &mut journal.redo,
+                        _ => {
+                            errors.push(format!("journal:{}: expected `undo` or `redo`", index + 1));
+                            continue;
+                        },
+                    };
+                    match parse_batch(parts.collect()) {
+                        Ok(batch)    => stack.push(batch),
+                        Err(message) => errors.push(format!("journal:{}: {}", index + 1, message)),
+                    }
+                },
+                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                Err(e) => errors.push(format!("Could not read {}: {}", file.display(), e)),
+            }
+        }
+        (journal, errors)
+    }
+
+    // A new operation makes the undone ones impossible to redo
+    pub fn record(&mut self, batch: Batch) -> Result<(), String> {
+        if batch.is_empty() { return Ok(()); }
+        self.redo.clear();
+        self.push_undo(batch);
+        self.save()
+    }
+
+    // Returns the description of what has been undone
+    pub fn undo(&mut self) -> Result<String, String> {
+        let batch = self.undo.pop().ok_or("Nothing to undo")?;
+        let description = describe(&batch);
+        let (reverted, errors) = revert_batch(batch);
+        if !reverted.is_empty() { self.redo.push(reverted); }
+        self.finish(errors).map(|_| description)
+    }
+
+    // Returns the description of what has been redone
+    pub fn redo(&mut self) -> Result<String, String> {
+        let batch = self.redo.pop().ok_or("Nothing to redo")?;
+        let (reverted, errors) = revert_batch(batch);
+        let description = describe(&reverted);
+        if !reverted.is_empty() { self.push_undo(reverted); }
+        self.finish(errors).map(|_| description)
+    }
+
+    fn push_undo(&mut self, batch: Batch) {
+        self.undo.push(batch);
+        if self.undo.len() > MAX_BATCHES { self.undo.remove(0); }
+    }
+
+    fn finish(&self, mut errors: Vec<String>) -> Result<(), String> {
+        if let Err(error) = self.save() { errors.push(error); }
+        if errors.is_empty() { Ok(()) } else { Err(errors.join("; ")) }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let file = self.file.as_ref().ok_or("Nowhere to save the journal")?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+        }
+        let mut contents = String::new();
+        for (stack, batches) in [("undo", &self.undo), ("redo", &self.redo)].iter() {
+            for batch in batches.iter() {
+                contents += stack;
+                for operation in batch.iter() {
+                    let (name, from, to) = parts_of(operation);
+                    contents += &format!(" {} {} {}", name, encode(from), encode(to));
+                }
+                contents += "\n";
+            }
+        }
+        fs::write(file, contents).map_err(|e| format!("Could not save the journal: {}", e))
+    }
+}
+
+pub fn default_journal_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("journal"))
+}
+
+// Returns the operation that has been performed
+fn revert(operation: &Operation) -> Result<Operation, String> {
+    let (_, _, to) = parts_of(operation);
+    if fs::symlink_metadata(to).is_err() {
+        return Err(format!("{} does not exist anymore", to.display()));
+    }
+    match operation {
+        Operation::Move { from, to } => {
+            if fs::symlink_metadata(from).is_ok() {
+                return Err(format!("Can not move back, {} already exists", from.display()));
+            }
+            move_now(to, from)?;
+            Ok(Operation::Move { from: to.clone(), to: from.clone() })
+        },
+        Operation::Copy { .. } | Operation::Restore { .. } | Operation::Create { .. } => {
+            let trashed = move_to_trash(to)?;
+            Ok(Operation::Trash { from: to.clone(), to: trashed })
+        },
+        Operation::Trash { .. } => {
+            let restored = restore(to)?;
+            Ok(Operation::Restore { from: to.clone(), to: restored })
+        },
+    }
+}
+
+// The latest operations get reverted first
+fn revert_batch(batch: Batch) -> (Batch, Vec<String>) {
+    let mut reverted = Vec::new();
+    let mut errors = Vec::new();
+    for operation in batch.iter().rev() {
+        match revert(operation) {
+            Ok(operation) => reverted.push(operation),
+            Err(error)    => errors.push(error),
+        }
+    }
+    reverted.reverse();
+    (reverted, errors)
+}
+
+// E.g. "moving notes.txt" or "trashing 3 entries"
+pub fn describe(batch: &Batch) -> String {
+    let verb = match batch.first() {
+        Some(Operation::Move    { .. }) => "moving",
+        Some(Operation::Copy    { .. }) => "copying",
+        Some(Operation::Trash   { .. }) => "trashing",
+        Some(Operation::Restore { .. }) => "restoring",
+        Some(Operation::Create  { .. }) => "creating",
+        None => return "nothing".to_string(),
+    };
+    if batch.len() == 1 {
+        let (_, from, _) = parts_of(&batch[0]);
+        format!("{} {}", verb, file_name(from))
+    } else {
+        format!("{} {} entries", verb, batch.len())
+    }
+}
+//-----------------------------------------------------------------------------
+fn parts_of(operation: &Operation) -> (&'static str, &PathBuf, &PathBuf) {
+    match operation {
+        Operation::Move    { from, to } => ("move",    from, to),
+        Operation::Copy    { from, to } => ("copy",    from, to),
+        Operation::Trash   { from, to } => ("trash",   from, to),
+        Operation::Restore { from, to } => ("restore", from, to),
+        Operation::Create  { path }     => ("create",  path, path), // keeps the format uniform
+    }
+}
+
+fn parse_batch(parts: Vec<&str>) -> Result<Batch, String> {
+    if parts.is_empty() || !parts.len().is_multiple_of(3) {
+        return Err("expected a list of `<operation> <from> <to>`".to_string());
+    }
+    parts.chunks(3).map(|chunk| {
+        let (from, to) = (decode(chunk[1]), decode(chunk[2]));
+        match chunk[0] {
+            "move"    => Ok(Operation::Move    { from, to }),
+            "copy"    => Ok(Operation::Copy    { from, to }),
+            "trash"   => Ok(Operation::Trash   { from, to }),
+            "restore" => Ok(Operation::Restore { from, to }),
+            "create"  => Ok(Operation::Create  { path: to }),
+            other     => Err(format!("unknown operation `{}`", other)),
+        }
+    }).collect()
+}
+
+fn encode(path: &Path) -> String {
+    percent_encode(path.as_os_str().as_bytes())
+}
+
+fn decode(text: &str) -> PathBuf {
+    PathBuf::from(OsString::from_vec(percent_decode(text)))
+}
@@ -0,0 +1,209 @@
+use crate::input::*;
+use crate::config::*;
+
+use std::path::PathBuf;
+use std::fs;
+
+
+// The keymap file is a list of lines of the form
+//
+//     map   <keys> <command> [arguments]
+//     unmap <keys>
+//
+// applied on top of the default bindings. Empty lines and lines starting
+// with '#' are ignored. Special keys are written as <tab>, <s-tab>, <space>
+// and <c-x> for Ctrl with a letter.
+//
+//     map   J  down 10
+//     map   gr goto /
+//     unmap dd
+//-----------------------------------------------------------------------------
+pub fn default_keymap_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("keymap"))
+}
+
+// Returns the resulting bindings along with the list of problems found in the file
+pub fn load_bindings(path: Option<&PathBuf>) -> (Vec<Binding>, Vec<String>) {
+    let mut bindings = default_bindings();
+    let mut errors = Vec::new();
+    if let Some(path) = path {
+        match fs::read_to_string(path) {
+            Ok(contents) => apply_keymap(&mut bindings, &contents, &mut errors),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => errors.push(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+    report_shadowed(&bindings, &mut errors);
+    (bindings, errors)
+}
+
+fn apply_keymap(bindings: &mut Vec<Binding>, contents: &str, errors: &mut Vec<String>) {
+    let mut mapped_here: Vec<Combination> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let line_number = index + 1;
+        let mut parts = line.split_whitespace();
+        let directive = parts.next().unwrap(); // the line is not empty
+        let result = parts.next()
+            .ok_or(format!("`{}` needs a key sequence", directive))
+            .and_then(combination_from)
+            .and_then(|combination| match directive {
+                "map" => {
+                    let name = parts.next().ok_or("`map` needs a command".to_string())?;
+                    let arguments = parts.collect::<Vec<&str>>().join(" ");
+                    let command = command_from(name, &arguments)?;
+                    if mapped_here.contains(&combination) {
+                        errors.push(format!("keymap:{}: `{}` is mapped more than once, the last one wins",
+                                            line_number, combination_to_string(&combination)));
+                    } else {
+                        mapped_here.push(combination.clone());
+                    }
+                    bind(bindings, combination, command);
+                    Ok(())
+                },
+                "unmap" => {
+                    if parts.next().is_some() {
+                        return Err("`unmap` takes only the key sequence".to_string());
+                    }
+                    let amount_before = bindings.len();
+                    bindings.retain(|(existing, _)| *existing != combination);
+                    if bindings.len() == amount_before {
+                        return Err(format!("`{}` is not mapped", combination_to_string(&combination)));
+                    }
+                    Ok(())
+                },
+                _ => Err(format!("unknown directive `{}` (expected map or unmap)", directive)),
+            });
+        if let Err(message) = result {
+            errors.push(format!("keymap:{}: {}", line_number, message));
+        }
+    }
+}
+
+fn bind(bindings: &mut Vec<Binding>, combination: Combination, command: Command) {
+    if let Some(binding) = bindings.iter_mut().find(|(existing, _)| *existing == combination) {
+        binding.1 = command;
+    } else {
+        bindings.push((combination, command));
+    }
+}
+
+// A combination that is a prefix of another one can never be triggered because
+// the input keeps waiting for the longer one
+fn report_shadowed(bindings: &[Binding], errors: &mut Vec<String>) {
+    for (shorter, _) in bindings.iter() {
+        if let Combination::Str(shorter) = shorter {
+            for (longer, _) in bindings.iter() {
+                if let Combination::Str(longer) = longer {
+                    if longer.len() > shorter.len() && longer.starts_with(shorter.as_str()) {
+                        errors.push(format!("keymap: `{}` is shadowed by `{}` and can never be triggered",
+                                            shorter, longer));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+//-----------------------------------------------------------------------------
+fn combination_from(keys: &str) -> Result<Combination, String> {
+    match keys {
+        "<tab>"   => return Ok(Combination::Tab),
+        "<s-tab>" => return Ok(Combination::ShiftTab),
+        _ => {},
+    }
+    let mut chars = keys.replace("<space>", " ");
+    for letter in b'a'..=b'z' {
+        let control = ((letter - b'a' + 1) as char).to_string();
+        chars = chars.replace(&format!("<c-{}>", letter as char), &control);
+    }
+    if chars.chars().count() > max_combination_len() {
+        return Err(format!("`{}` is longer than {} keys", keys, max_combination_len()));
+    }
+    Ok(Combination::Str(chars))
+}
+
+fn combination_to_string(combination: &Combination) -> String {
+    match combination {
+        Combination::Str(chars)   => chars.chars().map(|c| match c {
+            ' '                 => "<space>".to_string(),
+            '\x01'..='\x1a'     => format!("<c-{}>", (c as u8 - 1 + b'a') as char),
+            _                   => c.to_string(),
+        }).collect(),
+        Combination::Tab          => "<tab>".to_string(),
+        Combination::ShiftTab     => "<s-tab>".to_string(),
+    }
+}
+
+fn command_from(name: &str, arguments: &str) -> Result<Command, String> {
+    let no_arguments = |command: Command| {
+        if arguments.is_empty() { Ok(command) }
+        else { Err(format!("`{}` takes no arguments", name)) }
+    };
+    let count = || {
+        if arguments.is_empty() { Ok(1) }
+        else { arguments.parse::<u32>().map_err(|_| format!("`{}` is not a valid count", arguments)) }
+    };
+    match name {
+        "up"                  => Ok(Command::Up(count()?)),
+        "down"                => Ok(Command::Down(count()?)),
+        "left"                => no_arguments(Command::Left),
+        "right"               => no_arguments(Command::Right),
+        "sort"                => Ok(Command::Sort(parse_sorting_type(arguments)?)),
+        "sort_reverse"        => no_arguments(Command::ToggleSortReverse),
+        "sort_directories_first" => no_arguments(Command::ToggleDirectoriesFirst),
+        "goto"                => {
+            if arguments.is_empty() { return Err("`goto` needs a path".to_string()); }
+            Ok(Command::GoTo(arguments.to_string()))
+        },
+        "remove"              => no_arguments(Command::Remove),
+        "cut"                 => no_arguments(Command::Cut),
+        "update"              => no_arguments(Command::Update),
+        "yank"                => no_arguments(Command::Yank),
+        "paste"               => no_arguments(Command::Paste),
+        "cumulative_size"     => no_arguments(Command::CumulativeSize),
+        "select_under_cursor" => no_arguments(Command::SelectUnderCursor),
+        "invert_selection"    => no_arguments(Command::InvertSelection),
+        "clear_selection"     => no_arguments(Command::ClearSelection),
+        "select_matching"     => no_arguments(Command::SelectMatching),
+        "deselect_matching"   => no_arguments(Command::DeselectMatching),
+        "visual"              => no_arguments(Command::EnterVisualMode),
+        "new_tab"             => no_arguments(Command::NewTab),
+        "close_tab"           => no_arguments(Command::CloseTab),
+        "next_tab"            => no_arguments(Command::NextTab),
+        "previous_tab"        => no_arguments(Command::PreviousTab),
+        "search"              => no_arguments(Command::EnterSearchMode),
+        "find"                => no_arguments(Command::EnterFindMode),
+        "grep"                => no_arguments(Command::EnterGrepMode),
+        "change_name"         => no_arguments(Command::ChangeCurrentName),
+        "command"             => no_arguments(Command::EnterCommand),
+        "home"                => no_arguments(Command::Home),
+        "end"                 => no_arguments(Command::End),
+        "toggle_hidden"       => no_arguments(Command::ToggleHidden),
+        "filter"              => no_arguments(Command::EditFilter),
+        "clear_filter"        => no_arguments(Command::ClearFilter),
+        "goto_bookmark"       => no_arguments(Command::GoToBookmark),
+        "add_bookmark"        => no_arguments(Command::AddBookmark),
+        "remove_bookmark"     => no_arguments(Command::RemoveBookmark),
+        "show_transfers"      => no_arguments(Command::ShowTransfers),
+        "delete"              => no_arguments(Command::Delete),
+        "show_trash"          => no_arguments(Command::ShowTrash),
+        "restore"             => no_arguments(Command::Restore),
+        "undo"                => no_arguments(Command::Undo),
+        "redo"                => no_arguments(Command::Redo),
+        "bulk_rename"         => no_arguments(Command::BulkRename),
+        "pattern_rename"      => no_arguments(Command::PatternRename),
+        "create_file"         => no_arguments(Command::CreateFile),
+        "create_directory"    => no_arguments(Command::CreateDirectory),
+        "symlink"             => match arguments {
+            ""         => Ok(Command::CreateSymlink(false)),
+            "relative" => Ok(Command::CreateSymlink(true)),
+            _ => Err(format!("`symlink` takes only `relative`, not `{}`", arguments)),
+        },
+        "hard_link"           => no_arguments(Command::CreateHardLink),
+        "edit_permissions"    => no_arguments(Command::EditPermissions),
+        "show_details"        => no_arguments(Command::ShowDetails),
+        _ => Err(format!("unknown command `{}`", name)),
+    }
+}
@@ -12,7 +12,6 @@ mod system;
 use crate::system::*;
 
 mod filesystem;
-use crate::filesystem::*;
 
 mod input;
 use crate::input::*;
@@ -26,14 +25,38 @@ mod context;
 mod tab;
 mod notification;
 mod utils;
+mod config;
+use crate::config::*;
+mod keymap;
+use crate::keymap::*;
+mod bookmarks;
+use crate::bookmarks::*;
+mod cli;
+use crate::cli::*;
+mod transfer;
+mod trash;
+mod journal;
+mod bulk_rename;
+mod pattern_rename;
+mod create;
+mod permissions;
+mod details;
+mod find;
+mod grep;
+mod matching;
+mod select;
+use crate::journal::*;
+use crate::create::CreationKind;
 
-use std::path::PathBuf;
 
 
 #[derive(PartialEq, Eq)]
 enum Mode {
     Input,
     AwaitingCommand,
+    AwaitingBookmarkKey(BookmarkAction),
+    ResolvingConflict,
+    Visual, // only moving the cursor until the range is confirmed
 }
 
 struct Overseer {
@@ -42,56 +65,58 @@ struct Overseer {
 
     mode: Mode,
     current_input: Option<Combination>,
+    count: Option<usize>, // typed before a command, e.g. the 12 of `12j`
 
     possible_inputs: Matches, // const
     terminated: bool,
+    exit_state: Option<ExitState>,
 }
 
 impl Overseer {
-    fn init_system(starting_path: PathBuf) -> System {
-        System::new(
-            Settings {
-                columns_ratio: vec![2,3,3],
-                primary_paint: Paint::with_fg_bg(Color::White,  Color::Default),
-                preview_paint: Paint::with_fg_bg(Color::Green,  Color::Default),
-                paint_settings: PaintSettings {
-                    dir_paint:        Paint::with_fg_bg(Color::Cyan,   Color::Default).bold(),
-                    symlink_paint:    Paint::with_fg_bg(Color::Yellow, Color::Default).bold(),
-                    file_paint:       Paint::with_fg_bg(Color::White,  Color::Default),
-                    unknown_paint:    Paint::with_fg_bg(Color::Grey,   Color::White)  .bold(),
-                    executable_paint: Paint::with_fg_bg(Color::Green,  Color::Default).bold(),
-                },
-                scrolling_gap: 4,
-                copy_done_notification_delay_ms: 2000,
-            },
-            starting_path,
-        )
-    }
-
-    fn init() -> Overseer {
-        // let starting_path = PathBuf::from("/home/igorek/Stuff");
-        // let starting_path = PathBuf::from("/home/igorek/.config/google-chrome");
-        let mut starting_path = absolute_pathbuf();
-        starting_path.pop();
-        starting_path.pop();
-        starting_path.pop();
+    fn init(arguments: Arguments) -> Overseer {
+        let config_path = arguments.config_path.or(default_config_path());
+        let (mut settings, mut errors) = load_settings(config_path.as_ref());
+        if let Some(show_hidden)  = arguments.show_hidden  { settings.show_hidden  = show_hidden; }
+        if let Some(sorting_type) = arguments.sorting_type { settings.sorting.sorting_type = sorting_type; }
+        let (bindings, keymap_errors) = load_bindings(default_keymap_path().as_ref());
+        let (bookmarks, bookmarks_errors) = Bookmarks::load(default_bookmarks_path());
+        let (journal, journal_errors) = Journal::load(default_journal_path());
+        errors.extend(keymap_errors);
+        errors.extend(bookmarks_errors);
+        errors.extend(journal_errors);
+        let mut system = System::new(settings, bookmarks, journal, arguments.start_dir);
+        if let Some(name) = arguments.start_entry { system.move_cursor_to(&name); }
+        system.report_errors(&errors);
 
         Overseer {
             color_system: ColorSystem::new(),
-            system: Overseer::init_system(starting_path),
+            system,
             mode: Mode::AwaitingCommand,
-            possible_inputs: generate_possible_inputs(),
+            possible_inputs: generate_possible_inputs(&bindings),
             current_input: None,
+            count: None,
             terminated: false,
+            exit_state: None,
         }
     }
 
-    fn work(&mut self) {
+    // Consumes self so that the terminal is restored by the time this returns
+    fn work(mut self) -> Option<ExitState> {
         while !self.terminated {
+            self.system.set_pending_keys(self.pending_keys());
             self.system.draw(&mut self.color_system);
             self.maybe_draw_matches();
+            self.maybe_draw_bookmarks();
             self.handle_input();
         };
+        self.exit_state.take()
+    }
+
+    // The count and the combination typed so far, e.g. "3d"
+    fn pending_keys(&self) -> String {
+        let mut text = self.count.map(|count| count.to_string()).unwrap_or_default();
+        if let Some(Combination::Str(string)) = self.current_input.as_ref() { text += string; }
+        text
     }
 
     fn maybe_draw_matches(&mut self) {
@@ -108,27 +133,44 @@ impl Overseer {
         }
     }
 
+    fn maybe_draw_bookmarks(&mut self) {
+        if let Mode::AwaitingBookmarkKey(action) = self.mode {
+            self.system.draw_bookmarks(&mut self.color_system, action);
+        }
+    }
+
     fn handle_input(&mut self) {
         let input = self.system.get();
         if let Some(Input::EventResize) = input { self.system.resize(); }
         else if let Some(input) = input {
+            if (self.mode == Mode::AwaitingCommand || self.mode == Mode::Visual) && self.extends_count(&input) {
+                return;
+            }
             if self.mode == Mode::AwaitingCommand {
-                let combination = match input {
-                    Input::Tab      => Some(Combination::Tab),
-                    Input::ShiftTab => Some(Combination::ShiftTab),
-                    Input::Char(c)  => {
-                        if let Some(Combination::Str(mut string)) = self.current_input.take() {
-                            string.push(c);
-                            Some(Combination::Str(string))
-                        } else { Some(Combination::Str(c.to_string())) }
-                    },
-                    _ => None,
-                };
+                // Leaves the confirmed search or find, unless there is a combination or a count to drop
+                if input == Input::Escape && self.current_input.is_none() && self.count.is_none() {
+                    self.system.cancel_input();
+                }
+                let combination = self.combination_with(input);
                 self.current_input = self.handle_combination(combination);
+            } else if self.mode == Mode::Visual {
+                match input {
+                    Input::Escape    if self.current_input.is_none() && self.count.is_none()
+                                                                     => self.system.cancel_visual(),
+                    // The count has nothing to apply to then
+                    Input::Char('v') if self.current_input.is_none() => { self.count = None; self.system.finish_visual(true) },
+                    Input::Char('x') if self.current_input.is_none() => { self.count = None; self.system.finish_visual(false) },
+                    Input::Enter => { self.count = None; self.system.finish_visual(true) },
+                    _ => {
+                        let combination = self.combination_with(input);
+                        self.current_input = self.handle_combination(combination);
+                    },
+                }
             } else if self.mode == Mode::Input {
                 match input {
                     Input::Escape    => self.system.cancel_input(),
                     Input::Enter     => self.system.confirm_input(),
+                    Input::Char('\x14') => self.system.cycle_search_mode(), // <c-t>
                     Input::Char(c)   => self.system.insert_input(c),
                     Input::Backspace => self.system.remove_input_before_cursor(),
                     Input::Delete    => self.system.remove_input_under_cursor(),
@@ -140,50 +182,133 @@ impl Overseer {
                         self.handle_combination(Some(Combination::ShiftTab)),
                     _ => {},
                 };
+            } else if let Mode::AwaitingBookmarkKey(action) = self.mode {
+                if let Input::Char(c) = input {
+                    self.system.apply_bookmark_action(action, c);
+                }
+                self.mode = Mode::AwaitingCommand; // any other key cancels
+                return;
+            } else if self.mode == Mode::ResolvingConflict {
+                match input {
+                    Input::Char(c) => self.system.resolve_conflict_with_key(c),
+                    Input::Escape  => self.system.cancel_paste(),
+                    _ => {},
+                }
             }
-            self.mode = match self.system.inside_input_mode() {
-                true  => Mode::Input,
-                false => Mode::AwaitingCommand,
-            };
+            if let Mode::AwaitingBookmarkKey(_) = self.mode { return; } // was just requested
+            self.mode = if      self.system.resolving_conflict() { Mode::ResolvingConflict }
+                        else if self.system.inside_input_mode()  { Mode::Input }
+                        else if self.system.in_visual_mode()     { Mode::Visual }
+                        else                                     { Mode::AwaitingCommand };
+        }
+    }
+
+    // Digits before a combination make the count, unless they are bound themselves
+    fn extends_count(&mut self, input: &Input) -> bool {
+        let digit = match input {
+            Input::Char(c) if self.current_input.is_none() => c.to_digit(10),
+            _ => None,
+        };
+        match digit {
+            Some(0) if self.count.is_none() => false, // may be bound
+            Some(digit) if !self.possible_inputs.contains_key(&Combination::Str(digit.to_string())) => {
+                let count = self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize);
+                self.count = Some(count);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Extends the combination typed so far, if the input can be a part of one
+    fn combination_with(&mut self, input: Input) -> Option<Combination> {
+        match input {
+            Input::Tab      => Some(Combination::Tab),
+            Input::ShiftTab => Some(Combination::ShiftTab),
+            Input::Char(c)  => {
+                if let Some(Combination::Str(mut string)) = self.current_input.take() {
+                    string.push(c);
+                    Some(Combination::Str(string))
+                } else { Some(Combination::Str(c.to_string())) }
+            },
+            _ => None,
         }
     }
 
-    // Returns the new current_input
+    // Returns the new current_input; in the visual mode only the moves are carried out.
+    // The count goes with the command, or is dropped along with a wrong combination.
     fn handle_combination(&mut self, combination: Option<Combination>) -> Option<Combination> {
+        let count = self.count.take();
         if let Some(combination) = combination {
             if let Some(matches) = self.possible_inputs.get(&combination) {
-                if !exact_match(matches, &combination) { return Some(combination); }
-                let (_, command) = matches[0];
-                self.handle_command(&command);
+                if !exact_match(matches, &combination) {
+                    self.count = count;
+                    return Some(combination);
+                }
+                let (_, command) = matches[0].clone();
+                if self.mode == Mode::Visual && !moves_cursor_only(&command) { return None; }
+                self.handle_command(&command, count);
             }
         }
         None
     }
 
-    fn handle_command(&mut self, command: &Command) {
+    // Commands that take no count ignore it
+    fn handle_command(&mut self, command: &Command, count: Option<usize>) {
+        let times = count.unwrap_or(1);
         match command {
-            Command::Up(n)              => for _ in 0..*n {self.system.up()},
-            Command::Down(n)            => for _ in 0..*n {self.system.down()},
+            Command::Up(n)              => self.system.up_by((*n as usize).saturating_mul(times)),
+            Command::Down(n)            => self.system.down_by((*n as usize).saturating_mul(times)),
             Command::Left               => self.system.left(),
             Command::Right              => self.system.right(),
             Command::Sort(sorting_type) => self.system.sort_with(*sorting_type),
+            Command::ToggleSortReverse  => self.system.toggle_sort_reverse(),
+            Command::ToggleDirectoriesFirst => self.system.toggle_directories_first(),
             Command::GoTo(path)         => self.system.goto(path),
-            Command::Remove             => self.system.remove_selected(),
+            Command::Remove             => self.system.trash_selected(times),
+            Command::Delete             => self.system.delete_selected(times),
+            Command::ShowTrash          => self.system.show_trash(),
+            Command::Restore            => self.system.restore_selected(),
+            Command::Undo               => self.system.undo(),
+            Command::BulkRename         => self.system.bulk_rename(),
+            Command::Redo               => self.system.redo(),
             Command::Update             => self.system.update_current(),
-            Command::Yank               => self.system.yank_selected(),
-            Command::Cut                => self.system.cut_selected(),
+            Command::Yank               => self.system.yank_selected(times),
+            Command::Cut                => self.system.cut_selected(times),
             Command::Paste              => self.system.paste_into_current(),
             Command::CumulativeSize     => self.system.get_cumulative_size(),
-            Command::SelectUnderCursor  => self.system.select_under_cursor(),
+            Command::SelectUnderCursor  => self.system.toggle_following(times),
             Command::InvertSelection    => self.system.invert_selection(),
             Command::ClearSelection     => self.system.clear_selection(),
             Command::NewTab             => self.system.new_tab(),
-            Command::CloseTab           => self.terminated = self.system.close_tab(),
-            Command::NextTab            => self.system.next_tab(),
-            Command::PreviousTab        => self.system.previous_tab(),
-            Command::Home               => self.system.go_home(),
-            Command::End                => self.system.go_end(),
+            Command::CloseTab           => {
+                if self.system.on_last_tab() { self.exit_state = Some(self.system.exit_state()); }
+                self.terminated = self.system.close_tab();
+            },
+            Command::NextTab            => match count {
+                Some(number) => self.system.go_to_tab(number),
+                None         => self.system.next_tab(),
+            },
+            Command::PreviousTab        => match count {
+                Some(amount) => self.system.previous_tab_by(amount),
+                None         => self.system.previous_tab(),
+            },
+            Command::Home               => match count {
+                Some(line) => self.system.go_to_line(line),
+                None       => self.system.go_home(),
+            },
+            Command::End                => match count {
+                Some(line) => self.system.go_to_line(line),
+                None       => self.system.go_end(),
+            },
             Command::ToggleHidden       => self.system.toggle_hidden(),
+            Command::ClearFilter        => self.system.clear_filter(),
+            Command::EnterVisualMode    => self.system.start_visual(),
+            Command::GoToBookmark       => self.mode = Mode::AwaitingBookmarkKey(BookmarkAction::GoTo),
+            Command::AddBookmark        => self.mode = Mode::AwaitingBookmarkKey(BookmarkAction::Add),
+            Command::RemoveBookmark     => self.mode = Mode::AwaitingBookmarkKey(BookmarkAction::Remove),
+            Command::ShowTransfers      => self.system.toggle_transfers_popup(),
+            Command::ShowDetails        => self.system.toggle_details_popup(),
             Command::ChangeCurrentName  => {
                 self.mode = Mode::Input;
                 self.system.start_changing_current_name();
@@ -192,14 +317,68 @@ impl Overseer {
                 self.mode = Mode::Input;
                 self.system.start_search();
             },
+            Command::EnterFindMode      => {
+                self.mode = Mode::Input;
+                self.system.start_find();
+            },
+            Command::EnterGrepMode      => {
+                self.mode = Mode::Input;
+                self.system.start_grep();
+            },
             Command::EnterCommand       => {
                 self.mode = Mode::Input;
                 self.system.start_command();
             },
+            Command::SelectMatching     => {
+                self.mode = Mode::Input;
+                self.system.start_selecting_matching(true);
+            },
+            Command::DeselectMatching   => {
+                self.mode = Mode::Input;
+                self.system.start_selecting_matching(false);
+            },
+            Command::EditFilter         => {
+                self.mode = Mode::Input;
+                self.system.start_editing_filter();
+            },
+            Command::PatternRename      => {
+                self.mode = Mode::Input;
+                self.system.start_pattern_rename();
+            },
+            Command::CreateFile         => self.start_creating(CreationKind::File),
+            Command::CreateDirectory    => self.start_creating(CreationKind::Directory),
+            Command::CreateSymlink(relative) => self.start_creating(
+                CreationKind::Symlink { targets: Vec::new(), relative: *relative }),
+            Command::CreateHardLink     => self.start_creating(CreationKind::HardLink(Vec::new())),
+            Command::EditPermissions    => {
+                self.mode = Mode::Input;
+                self.system.start_editing_permissions();
+            },
         }
     }
+
+    // The targets of the links are filled in from the selection
+    fn start_creating(&mut self, kind: CreationKind) {
+        self.mode = Mode::Input;
+        self.system.start_creating(kind);
+    }
 }
 
 fn main() {
-    Overseer::init().work();
+    match parse_arguments(std::env::args().skip(1)) {
+        Ok(ParsedArguments::Run(arguments)) => {
+            let shell_outputs = arguments.shell_outputs.clone();
+            if let Some(exit_state) = Overseer::init(arguments).work() {
+                if let Err(error) = write_shell_outputs(&shell_outputs, &exit_state) {
+                    eprintln!("vffm: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Ok(ParsedArguments::Exit(text))     => println!("{}", text),
+        Err(error) => {
+            eprintln!("vffm: {}\nTry `vffm --help` for more information.", error);
+            std::process::exit(2);
+        },
+    }
 }
@@ -0,0 +1,341 @@
+use crate::direntry::*;
+
+use regex::{Regex, RegexBuilder};
+
+
+// The ways the search filters the siblings by their names. All of them ignore
+// the case unless the query has an uppercase letter. Fuzzy puts the best
+// matches first, the others keep the sorting.
+//-----------------------------------------------------------------------------
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchMode {
+    Substring,
+    Fuzzy,
+    Regex,
+    Glob,
+    Prefix,
+}
+
+pub fn next_search_mode(mode: SearchMode) -> SearchMode {
+    match mode {
+        SearchMode::Substring => SearchMode::Fuzzy,
+        SearchMode::Fuzzy     => SearchMode::Regex,
+        SearchMode::Regex     => SearchMode::Glob,
+        SearchMode::Glob      => SearchMode::Prefix,
+        SearchMode::Prefix    => SearchMode::Substring,
+    }
+}
+
+pub fn search_mode_name(mode: SearchMode) -> &'static str {
+    match mode {
+        SearchMode::Substring => "substring",
+        SearchMode::Fuzzy     => "fuzzy",
+        SearchMode::Regex     => "regex",
+        SearchMode::Glob      => "glob",
+        SearchMode::Prefix    => "prefix",
+    }
+}
+
+struct Matcher {
+    mode: SearchMode,
+    query: String,
+    case_sensitive: bool,
+    regex: Option<Regex>, // for Regex and Glob, None if the query is invalid
+}
+
+struct Match {
+    score: i64,
+    positions: Vec<usize>, // of the matched chars in the name
+}
+
+impl Matcher {
+    fn new(mode: SearchMode, query: &str) -> Matcher {
+        let case_sensitive = query.to_lowercase() != query;
+        let regex = match mode {
+            SearchMode::Regex => Some(query.to_string()),
+            SearchMode::Glob  => Some(regex_from_glob(query)),
+            _                 => None,
+        }.and_then(|pattern| RegexBuilder::new(&pattern)
+                   .case_insensitive(!case_sensitive).build().ok());
+        Matcher {
+            mode,
+            query: if case_sensitive { query.to_string() } else { query.to_lowercase() },
+            case_sensitive,
+            regex,
+        }
+    }
+
+    fn matches(&self, name: &str) -> Option<Match> {
+        if self.query.is_empty() { return Some(Match { score: 0, positions: Vec::new() }); }
+        match self.mode {
+            SearchMode::Regex | SearchMode::Glob => {
+                let found = self.regex.as_ref()?.find(name)?;
+                let positions = char_positions_of_bytes(name, found.start(), found.end());
+                Some(Match { score: 0, positions })
+            },
+            _ => {
+                // Char by char, so that the positions stay those of the name
+                let name: Vec<char> = if self.case_sensitive { name.chars().collect() }
+                                      else                   { name.chars().map(fold_case).collect() };
+                let query: Vec<char> = self.query.chars().collect();
+                match self.mode {
+                    SearchMode::Fuzzy     => fuzzy_match(&name, &query),
+                    SearchMode::Prefix    => {
+                        if !name.starts_with(&query) { return None; }
+                        Some(Match { score: 0, positions: (0..query.len()).collect() })
+                    },
+                    _ => { // Substring
+                        let start = name.windows(query.len()).position(|window| window == &query[..])?;
+                        Some(Match { score: 0, positions: (start..start + query.len()).collect() })
+                    },
+                }
+            },
+        }
+    }
+}
+
+// Some chars get longer in lowercase, `İ` becomes `i̇`, only the first one is kept
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// Returns the entries that match, with the matched chars to highlight
+pub fn filter_entries(entries: &[DirEntry], mode: SearchMode, query: &str) -> Vec<DirEntry> {
+    let matcher = Matcher::new(mode, query);
+    let mut scored: Vec<(i64, DirEntry)> = entries.iter().filter_map(|entry| {
+        let Match {score, positions} = matcher.matches(&entry.name)?;
+        let mut entry = entry.clone();
+        entry.highlighted = positions;
+        Some((score, entry))
+    }).collect();
+    if mode == SearchMode::Fuzzy {
+        scored.sort_by(|(a, _), (b, _)| b.cmp(a)); // stable, so the ties stay sorted
+    }
+    scored.into_iter().map(|(_, entry)| entry).collect()
+}
+
+//-----------------------------------------------------------------------------
+// Takes every query char at its first possible place, then tries to start
+// later for a better score. Consecutive chars and chars that begin a word
+// score more, the skipped chars cost a little.
+fn fuzzy_match(name: &[char], query: &[char]) -> Option<Match> {
+    let mut best: Option<Match> = None;
+    for start in 0..name.len() {
+        if name[start] != query[0] { continue; }
+        let positions = match subsequence_from(name, query, start) {
+            Some(positions) => positions,
+            None            => break, // no later start can match either
+        };
+        let score = fuzzy_score(name, &positions);
+        if best.as_ref().is_none_or(|best| score > best.score) {
+            best = Some(Match { score, positions });
+        }
+    }
+    best
+}
+
+fn subsequence_from(name: &[char], query: &[char], start: usize) -> Option<Vec<usize>> {
+    let mut positions = Vec::with_capacity(query.len());
+    let mut index = start;
+    for &c in query {
+        while index < name.len() && name[index] != c { index += 1; }
+        if index == name.len() { return None; }
+        positions.push(index);
+        index += 1;
+    }
+    Some(positions)
+}
+
+fn fuzzy_score(name: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    for (index, &position) in positions.iter().enumerate() {
+        score += 1;
+        if position == 0 || !name[position - 1].is_alphanumeric() { score += 8; }
+        if index > 0 {
+            let gap = position - positions[index - 1] - 1;
+            if gap == 0 { score += 5; } else { score -= gap.min(10) as i64; }
+        }
+    }
+    score - positions[0].min(10) as i64
+}
+
+// `*` is any run of chars, `?` is one char, `[...]` is kept as a class (`[!...]` negates)
+fn regex_from_glob(glob: &str) -> String {
+    let mut result = "^".to_string();
+    let mut in_class = false;
+    let mut class_opened = false; // by the previous char
+    for c in glob.chars() {
+        let first_in_class = class_opened;
+        class_opened = false;
+        match c {
+            '*' if !in_class => result.push_str(".*"),
+            '?' if !in_class => result.push('.'),
+            '[' if !in_class => { in_class = true; class_opened = true; result.push(c); },
+            '!' if first_in_class => result.push('^'), // `[!a-z]` is `[^a-z]`
+            ']' if in_class  => { in_class = false; result.push(c); },
+            _   if in_class  => result.push(c),
+            _                => result.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+fn char_positions_of_bytes(string: &str, begin: usize, end: usize) -> Vec<usize> {
+    string.char_indices().enumerate()
+        .filter(|(_, (byte, _))| *byte >= begin && *byte < end)
+        .map(|(position, _)| position).collect()
+}
+
+// Whether the query can be used in this mode, for the search bar
+pub fn query_is_valid(mode: SearchMode, query: &str) -> bool {
+    match mode {
+        SearchMode::Regex | SearchMode::Glob => Matcher::new(mode, query).regex.is_some(),
+        _                                    => true,
+    }
+}
+
+// A filter on the names that stays with the tab while moving around: a glob,
+// or a regex between slashes (`/\.rs$/`, or `/.../i` to ignore the case) like
+// the grep takes it. Directories always pass, so that they can be entered.
+//-----------------------------------------------------------------------------
+#[derive(Clone)]
+pub struct NameFilter {
+    pub text: String,
+    regex: Regex,
+}
+
+impl NameFilter {
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+pub fn parse_name_filter(text: &str) -> Result<NameFilter, String> {
+    let ignore_case = text.len() > 2 && text.starts_with('/') && text.ends_with("/i");
+    let regex = if ignore_case || (text.len() >= 2 && text.starts_with('/') && text.ends_with('/')) {
+        let end = if ignore_case { text.len() - 2 } else { text.len() - 1 };
+        RegexBuilder::new(&text[1..end]).case_insensitive(ignore_case).build()
+    } else {
+        let case_sensitive = text.to_lowercase() != text;
+        RegexBuilder::new(&regex_from_glob(text)).case_insensitive(!case_sensitive).build()
+    }.map_err(|e| format!("Invalid filter: {}", e.to_string().lines().last().unwrap_or("")))?;
+    Ok(NameFilter { text: text.to_string(), regex })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coloring::*;
+    use crate::filesystem::*;
+
+    fn chars(string: &str) -> Vec<char> {
+        string.chars().collect()
+    }
+
+    fn positions(mode: SearchMode, query: &str, name: &str) -> Option<Vec<usize>> {
+        Matcher::new(mode, query).matches(name).map(|found| found.positions)
+    }
+
+    #[test]
+    fn fuzzy_match_takes_the_chars_in_order() {
+        let found = fuzzy_match(&chars("foo_bar"), &chars("fb")).unwrap();
+        assert_eq!(found.positions, vec![0, 4]);
+        assert!(fuzzy_match(&chars("foo_bar"), &chars("bf")).is_none());
+        assert!(fuzzy_match(&chars("foo"), &chars("fooo")).is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_prefers_a_later_start_that_scores_more() {
+        // The first `b` leaves a gap, the one starting a word is followed right away
+        let found = fuzzy_match(&chars("abxxc_bc"), &chars("bc")).unwrap();
+        assert_eq!(found.positions, vec![6, 7]);
+    }
+
+    #[test]
+    fn fuzzy_score_orders_the_matches() {
+        let consecutive = fuzzy_score(&chars("abc"), &[0, 1]);
+        let gapped      = fuzzy_score(&chars("axbc"), &[0, 2]);
+        assert!(consecutive > gapped);
+        let word_start  = fuzzy_score(&chars("x_ab"), &[2, 3]);
+        let inside      = fuzzy_score(&chars("xyab"), &[2, 3]);
+        assert!(word_start > inside);
+        let early       = fuzzy_score(&chars("ab"), &[0, 1]);
+        let late        = fuzzy_score(&chars("x ab"), &[2, 3]);
+        assert!(early > late);
+    }
+
+    #[test]
+    fn fuzzy_results_come_best_first() {
+        let entries: Vec<DirEntry> = vec!["xfxxxb", "foo_bar", "nothing"].into_iter()
+            .map(|name| DirEntry {
+                entrytype: EntryType::Regular,
+                name: name.to_string(),
+                size: 0, time_modified: 0, time_changed: 0, time_accessed: 0,
+                paint: Paint::with_fg_bg(Color::Default, Color::Default),
+                is_selected: false,
+                highlighted: Vec::new(),
+            }).collect();
+        let names: Vec<String> = filter_entries(&entries, SearchMode::Fuzzy, "fb")
+            .into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, vec!["foo_bar", "xfxxxb"]);
+    }
+
+    #[test]
+    fn regex_from_glob_translates_the_wildcards() {
+        assert_eq!(regex_from_glob("*.rs"), r"^.*\.rs$");
+        assert_eq!(regex_from_glob("file?.txt"), r"^file.\.txt$");
+        assert_eq!(regex_from_glob("a+b"), r"^a\+b$");
+    }
+
+    #[test]
+    fn regex_from_glob_keeps_the_classes() {
+        assert_eq!(regex_from_glob("[ch]"), "^[ch]$");
+        assert_eq!(regex_from_glob("[*?]x"), "^[*?]x$"); // no wildcards inside
+        assert_eq!(regex_from_glob("[!a-z]*"), "^[^a-z].*$");
+        assert_eq!(regex_from_glob("[a!]"), "^[a!]$"); // only the leading one negates
+        assert_eq!(regex_from_glob("[ab][!c]"), "^[ab][^c]$");
+        assert!(positions(SearchMode::Glob, "[!.]*", "main.c").is_some());
+        assert!(positions(SearchMode::Glob, "[!.]*", ".hidden").is_none());
+        assert!(positions(SearchMode::Glob, "*.[ch]", "main.c").is_some());
+        assert!(positions(SearchMode::Glob, "*.[ch]", "main.rs").is_none());
+    }
+
+    #[test]
+    fn char_positions_of_bytes_counts_chars() {
+        assert_eq!(char_positions_of_bytes("abc", 1, 3), vec![1, 2]);
+        assert_eq!(char_positions_of_bytes("äbc", 2, 3), vec![1]); // `ä` takes 2 bytes
+        assert_eq!(char_positions_of_bytes("abc", 1, 1), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        for &mode in [SearchMode::Substring, SearchMode::Fuzzy, SearchMode::Regex,
+                      SearchMode::Glob, SearchMode::Prefix].iter() {
+            assert_eq!(positions(mode, "", "name"), Some(Vec::new()));
+        }
+    }
+
+    #[test]
+    fn invalid_query_matches_nothing() {
+        assert!(!query_is_valid(SearchMode::Regex, "a("));
+        assert!(!query_is_valid(SearchMode::Glob, "[a"));
+        assert!(query_is_valid(SearchMode::Fuzzy, "a("));
+        assert_eq!(positions(SearchMode::Regex, "a(", "a("), None);
+    }
+
+    #[test]
+    fn case_is_ignored_unless_the_query_has_uppercase() {
+        assert_eq!(positions(SearchMode::Substring, "rea", "README"), Some(vec![0, 1, 2]));
+        assert_eq!(positions(SearchMode::Substring, "Rea", "README"), None);
+        assert_eq!(positions(SearchMode::Prefix, "read", "ReadMe"), Some(vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn positions_stay_those_of_the_name() {
+        // `İ` lowercases to two chars
+        assert_eq!(positions(SearchMode::Substring, "stan", "İstanbul"), Some(vec![1, 2, 3, 4]));
+        assert_eq!(positions(SearchMode::Fuzzy, "sb", "İstanbul"), Some(vec![1, 5]));
+        assert_eq!(positions(SearchMode::Prefix, "ist", "İstanbul"), Some(vec![0, 1, 2]));
+    }
+}
@@ -0,0 +1,241 @@
+use regex::{Regex, RegexBuilder};
+
+
+// A pattern is one of
+//
+//     s/regex/replacement/[gi]   substitution, `$1` refers to a group; any delimiter may follow `s`
+//     upper | lower | title      case change of the whole name
+//     photo_{n:03}.{ext}         template: {n} counts from 1 (`:03` pads with zeros),
+//                                {name} is the name without extension, {ext} the extension
+//-----------------------------------------------------------------------------
+pub enum RenamePattern {
+    Substitute { regex: Regex, replacement: String, all: bool },
+    Upper,
+    Lower,
+    Title,
+    Template(String),
+}
+
+pub fn parse_pattern(text: &str) -> Result<RenamePattern, String> {
+    match text {
+        ""      => Err("Empty pattern".to_string()),
+        "upper" => Ok(RenamePattern::Upper),
+        "lower" => Ok(RenamePattern::Lower),
+        "title" => Ok(RenamePattern::Title),
+        _ if is_substitution(text) => parse_substitution(text),
+        _ => {
+            apply_template(text, "name", 1)?; // to report the mistakes right away
+            Ok(RenamePattern::Template(text.to_string()))
+        },
+    }
+}
+
+// The number is the position of the entry among the renamed ones, from 1
+pub fn apply_pattern(pattern: &RenamePattern, name: &str, number: usize) -> String {
+    match pattern {
+        RenamePattern::Substitute { regex, replacement, all } => {
+            if *all { regex.replace_all(name, replacement.as_str()).to_string() }
+            else    { regex.replace(name, replacement.as_str()).to_string() }
+        },
+        RenamePattern::Upper => name.to_uppercase(),
+        RenamePattern::Lower => name.to_lowercase(),
+        RenamePattern::Title => title_case(name),
+        RenamePattern::Template(template) =>
+            apply_template(template, name, number).unwrap_or_default(), // checked when parsed
+    }
+}
+
+fn is_substitution(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next() == Some('s') && chars.next().is_some_and(|c| !c.is_alphanumeric() && c != ' ')
+}
+
+fn parse_substitution(text: &str) -> Result<RenamePattern, String> {
+    let delimiter = text[1..].chars().next().unwrap(); // checked by is_substitution
+    let parts = split_unescaped(&text[1 + delimiter.len_utf8()..], delimiter);
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err(format!("Expected s{0}regex{0}replacement{0}[flags]", delimiter));
+    }
+    let flags = parts.get(2).map(|flags| flags.as_str()).unwrap_or("");
+    if let Some(flag) = flags.chars().find(|&c| c != 'g' && c != 'i') {
+        return Err(format!("Unknown flag `{}`, expected g or i", flag));
+    }
+    let regex = RegexBuilder::new(&parts[0])
+        .case_insensitive(flags.contains('i'))
+        .build()
+        .map_err(|e| format!("Invalid regex: {}", e.to_string().lines().last().unwrap_or("")))?;
+    Ok(RenamePattern::Substitute { regex, replacement: parts[1].clone(), all: flags.contains('g') })
+}
+
+// `\` followed by the delimiter stands for the delimiter itself
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&delimiter) {
+            parts.last_mut().unwrap().push(chars.next().unwrap());
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    parts
+}
+
+fn apply_template(template: &str, name: &str, number: usize) -> Result<String, String> {
+    let (stem, ext) = split_extension(name);
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}')
+            .ok_or(format!("Unclosed `{{` in `{}`", template))? + start;
+        result += &rest[..start];
+        let placeholder = &rest[start + 1..end];
+        match placeholder {
+            "name" => result += stem,
+            "ext"  => {
+                if ext.is_empty() && result.ends_with('.') { result.pop(); } // no dangling dot
+                result += ext;
+            },
+            "n"    => result += &number.to_string(),
+            _ if placeholder.starts_with("n:") => {
+                let width = placeholder[2..].parse::<usize>()
+                    .map_err(|_| format!("Invalid width in `{{{}}}`", placeholder))?;
+                result += &format!("{:0width$}", number, width = width);
+            },
+            _ => return Err(format!("Unknown placeholder `{{{}}}`", placeholder)),
+        }
+        rest = &rest[end + 1..];
+    }
+    result += rest;
+    Ok(result)
+}
+
+// A leading dot does not start an extension, e.g. `.bashrc`
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(index) if index > 0 => (&name[..index], &name[index + 1..]),
+        _                        => (name, ""),
+    }
+}
+
+fn title_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut word_start = true;
+    for c in name.chars() {
+        if word_start { result.extend(c.to_uppercase()); }
+        else          { result.extend(c.to_lowercase()); }
+        word_start = !c.is_alphanumeric();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renamed(pattern: &str, name: &str, number: usize) -> String {
+        match parse_pattern(pattern) {
+            Ok(pattern) => apply_pattern(&pattern, name, number),
+            Err(error)  => panic!("`{}` should parse: {}", pattern, error),
+        }
+    }
+
+    fn error_of(pattern: &str) -> String {
+        match parse_pattern(pattern) {
+            Ok(_)      => panic!("`{}` should not parse", pattern),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn substitution_replaces_the_first_match_unless_global() {
+        assert_eq!(renamed("s/a/o/", "banana", 1), "bonana");
+        assert_eq!(renamed("s/a/o/g", "banana", 1), "bonono");
+        assert_eq!(renamed("s/A/o/gi", "bAnana", 1), "bonono");
+        assert_eq!(renamed("s/a/o", "banana", 1), "bonana"); // the last delimiter is optional
+        assert_eq!(renamed("s/x/o/", "banana", 1), "banana");
+        assert_eq!(renamed("s/n/", "banana", 1), "baana"); // an empty replacement removes
+    }
+
+    #[test]
+    fn substitution_refers_to_groups() {
+        assert_eq!(renamed(r"s/(\w+)_(\d+)/$2-$1/", "photo_12.jpg", 1), "12-photo.jpg");
+        assert_eq!(renamed(r"s/^/${1}x/", "a", 1), "xa"); // a missing group is empty
+    }
+
+    #[test]
+    fn substitution_takes_any_delimiter() {
+        assert_eq!(renamed("s|/|_|g", "a/b", 1), "a_b");
+        assert_eq!(renamed("s#a#b#", "a", 1), "b");
+        assert_eq!(renamed("s→a→b→", "a", 1), "b"); // multi-byte
+    }
+
+    #[test]
+    fn escaped_delimiters_stay_in_place() {
+        assert_eq!(split_unescaped(r"a\/b/c", '/'), vec!["a/b", "c"]);
+        assert_eq!(split_unescaped(r"a\.b", '/'), vec![r"a\.b"]); // other escapes are for the regex
+        assert_eq!(split_unescaped("", '/'), vec![""]);
+        assert_eq!(renamed(r"s/\//-/g", "a", 1), "a");
+        assert_eq!(renamed(r"s/x/\//", "axb", 1), "a/b");
+        assert_eq!(renamed(r"s/\./_/g", "a.b.c", 1), "a_b_c");
+    }
+
+    #[test]
+    fn bad_substitutions_are_reported() {
+        assert_eq!(error_of("s/a"), "Expected s/regex/replacement/[flags]");
+        assert_eq!(error_of("s/a/b/g/x"), "Expected s/regex/replacement/[flags]");
+        assert_eq!(error_of("s/a/b/x"), "Unknown flag `x`, expected g or i");
+        assert!(error_of("s/(/b/").starts_with("Invalid regex: "));
+    }
+
+    #[test]
+    fn only_s_with_a_delimiter_is_a_substitution() {
+        assert!(is_substitution("s/a/b/"));
+        assert!(!is_substitution("s"));
+        assert!(!is_substitution("sa/b/"));
+        assert!(!is_substitution("s a"));
+        assert_eq!(renamed("so_{n}", "x", 4), "so_4"); // a template then
+    }
+
+    #[test]
+    fn case_changes() {
+        assert_eq!(renamed("upper", "Ab.txt", 1), "AB.TXT");
+        assert_eq!(renamed("lower", "Ab.TXT", 1), "ab.txt");
+        assert_eq!(renamed("title", "the QUICK-brown fox.txt", 1), "The Quick-Brown Fox.Txt");
+    }
+
+    #[test]
+    fn template_placeholders() {
+        assert_eq!(renamed("{name}_{n}.{ext}", "photo.jpg", 7), "photo_7.jpg");
+        assert_eq!(renamed("photo_{n:03}.{ext}", "x.png", 7), "photo_007.png");
+        assert_eq!(renamed("{n:2}", "x", 123), "123"); // the width is the least
+        assert_eq!(renamed("{name}", "archive.tar.gz", 1), "archive.tar");
+        assert_eq!(renamed("{ext}", "archive.tar.gz", 1), "gz");
+    }
+
+    #[test]
+    fn template_leaves_no_dangling_dot() {
+        assert_eq!(renamed("{name}.{ext}", "README", 1), "README");
+        assert_eq!(renamed("{name}.{ext}", ".bashrc", 1), ".bashrc");
+        assert_eq!(renamed("{name}.bak", "README", 1), "README.bak");
+    }
+
+    #[test]
+    fn bad_templates_are_reported() {
+        assert_eq!(error_of(""), "Empty pattern");
+        assert_eq!(error_of("a_{n"), "Unclosed `{` in `a_{n`");
+        assert_eq!(error_of("{n:x}"), "Invalid width in `{n:x}`");
+        assert_eq!(error_of("{size}"), "Unknown placeholder `{size}`");
+    }
+
+    #[test]
+    fn split_extension_keeps_a_leading_dot() {
+        assert_eq!(split_extension("a.b"), ("a", "b"));
+        assert_eq!(split_extension(".a"), (".a", ""));
+        assert_eq!(split_extension(".a.b"), (".a", "b"));
+        assert_eq!(split_extension("a."), ("a", ""));
+        assert_eq!(split_extension("a"), ("a", ""));
+    }
+}
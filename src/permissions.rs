@@ -0,0 +1,242 @@
+use crate::filesystem::*;
+
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::ffi::{CStr, CString};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+
+// The editor starts from the mode, owner and group of the first target. Only
+// the bits that have been touched (toggled or typed in octal) are applied, so
+// the rest of the bits of every target stays as it was.
+//-----------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EditedField {
+    Mode,
+    Owner,
+    Group,
+}
+
+#[derive(Clone)]
+pub struct PermissionsEditor {
+    pub targets: Vec<PathBuf>,
+    pub mode: u32,
+    pub touched: u32, // the bits of the mode that get applied
+    pub class: usize, // 0 is the owner, 1 the group, 2 the others
+    pub octal: String,
+    pub owner: String,
+    pub group: String,
+    pub field: EditedField,
+    pub recursive: bool,
+    initial_owner: String,
+    initial_group: String,
+}
+
+const CLASS_NAMES: [&str; 3] = ["owner", "group", "others"];
+
+impl PermissionsEditor {
+    pub fn new(targets: Vec<PathBuf>) -> Result<PermissionsEditor, String> {
+        let first = targets.first().ok_or("Nothing to change")?;
+        let metadata = fs::symlink_metadata(first)
+            .map_err(|e| format!("Could not read {}: {}", first.display(), e))?;
+        let owner = user_name(metadata.uid());
+        let group = group_name(metadata.gid());
+        Ok(PermissionsEditor {
+            targets,
+            mode: metadata.mode() & 0o7777,
+            touched: 0,
+            class: 0,
+            octal: String::new(),
+            owner: owner.clone(),
+            group: group.clone(),
+            field: EditedField::Mode,
+            recursive: false,
+            initial_owner: owner,
+            initial_group: group,
+        })
+    }
+
+    // Other keys are ignored
+    pub fn handle_key(&mut self, c: char) {
+        if self.field != EditedField::Mode {
+            let name = if self.field == EditedField::Owner { &mut self.owner } else { &mut self.group };
+            if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' { name.push(c); }
+            return;
+        }
+        match c {
+            'h'       => self.class = self.class.saturating_sub(1),
+            'l'       => self.class = (self.class + 1).min(2),
+            'r'       => self.toggle(4),
+            'w'       => self.toggle(2),
+            'x'       => self.toggle(1),
+            'R'       => self.recursive = !self.recursive,
+            'o'       => { self.field = EditedField::Owner; self.owner.clear(); },
+            'g'       => { self.field = EditedField::Group; self.group.clear(); },
+            '0'..='7' => self.type_octal(c),
+            _ => {},
+        }
+    }
+
+    pub fn remove_last(&mut self) {
+        match self.field {
+            EditedField::Mode  => { self.octal.pop(); },
+            EditedField::Owner => { self.owner.pop(); },
+            EditedField::Group => { self.group.pop(); },
+        }
+    }
+
+    // An emptied name means that it stays as it was
+    pub fn finish_name(&mut self) {
+        if self.owner.is_empty() { self.owner = self.initial_owner.clone(); }
+        if self.group.is_empty() { self.group = self.initial_group.clone(); }
+        self.field = EditedField::Mode;
+    }
+
+    fn toggle(&mut self, bit: u32) {
+        let bit = bit << (3 * (2 - self.class));
+        self.mode ^= bit;
+        self.touched |= bit;
+        self.octal.clear();
+    }
+
+    // Three digits set the rwx bits, four digits also the setuid, setgid and sticky ones
+    fn type_octal(&mut self, digit: char) {
+        if self.octal.len() == 4 { self.octal.clear(); }
+        self.octal.push(digit);
+        let value = u32::from_str_radix(&self.octal, 8).unwrap(); // only digits from 0 to 7
+        match self.octal.len() {
+            3 => { self.mode = (self.mode & 0o7000) | value; self.touched |= 0o777; },
+            4 => { self.mode = value; self.touched |= 0o7777; },
+            _ => {},
+        }
+    }
+
+    pub fn title(&self) -> String {
+        if self.targets.len() == 1 { format!("Permissions of {}", file_name(&self.targets[0])) }
+        else                       { format!("Permissions of {} entries", self.targets.len()) }
+    }
+
+    pub fn rows(&self) -> Vec<(String, String)> {
+        let mut classes = Vec::new();
+        for class in 0..3 {
+            let special = (self.mode >> 9) & (4 >> class) != 0;
+            let letter = if class == 2 { 't' } else { 's' };
+            let bits = permission_number_to_string_representation(
+                (self.mode >> (3 * (2 - class))) & 7, special, letter);
+            let editing = self.field == EditedField::Mode && class == self.class;
+            classes.push(if editing { format!("[{}]", bits) } else { format!(" {} ", bits) });
+        }
+        let typed = if self.octal.is_empty() { String::new() } else { format!("  typed {}", self.octal) };
+        let cursor = |field| if self.field == field { "_" } else { "" };
+        let mut rows = vec![
+            ("mode".to_string(), format!("{}  {:04o}  ({}){}", classes.concat(), self.mode,
+                                         CLASS_NAMES[self.class], typed)),
+            ("owner".to_string(), format!("{}{}", self.owner, cursor(EditedField::Owner))),
+            ("group".to_string(), format!("{}{}", self.group, cursor(EditedField::Group))),
+        ];
+        if self.targets.iter().any(|path| is_dir(path) && !is_symlink(path)) {
+            let recursive = if self.recursive { "yes" } else { "no" };
+            rows.push(("recursive".to_string(), recursive.to_string()));
+        }
+        rows.push(("".to_string(), "".to_string()));
+        if self.field == EditedField::Mode {
+            rows.push(("h l".to_string(),     "Choose the owner, group or others".to_string()));
+            rows.push(("r w x".to_string(),   "Toggle reading, writing or executing".to_string()));
+            rows.push(("0-7".to_string(),     "Type the mode in octal".to_string()));
+            rows.push(("o g".to_string(),     "Type a new owner or group".to_string()));
+            rows.push(("R".to_string(),       "Toggle applying to the contents of directories".to_string()));
+            rows.push(("Enter".to_string(),   "Apply".to_string()));
+        } else {
+            rows.push(("Enter".to_string(),   "Done with the name (empty keeps the old one)".to_string()));
+        }
+        rows.push(("Esc".to_string(), "Cancel".to_string()));
+        rows
+    }
+
+    // Returns the amount of changed entries and the failures
+    pub fn apply(&self) -> Result<(usize, Vec<String>), String> {
+        let uid = if self.owner == self.initial_owner { None } else { Some(user_id(&self.owner)?) };
+        let gid = if self.group == self.initial_group { None } else { Some(group_id(&self.group)?) };
+        if self.touched == 0 && uid.is_none() && gid.is_none() {
+            return Err("Nothing has been changed".to_string());
+        }
+        let mut changed = 0;
+        let mut errors = Vec::new();
+        for path in self.targets.iter() {
+            self.apply_to(path, uid, gid, &mut changed, &mut errors);
+        }
+        Ok((changed, errors))
+    }
+
+    // Symlinks are not followed, only their ownership changes
+    fn apply_to(&self, path: &PathBuf, uid: Option<u32>, gid: Option<u32>,
+                changed: &mut usize, errors: &mut Vec<String>) {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => return errors.push(format!("Could not read {}: {}", path.display(), e)),
+        };
+        let is_symlink = metadata.file_type().is_symlink();
+        let mut result = Ok(());
+        if self.touched != 0 && !is_symlink {
+            let mode = (metadata.mode() & 0o7777 & !self.touched) | (self.mode & self.touched);
+            result = fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("Could not change the mode of {}: {}", path.display(), e));
+        }
+        if result.is_ok() && (uid.is_some() || gid.is_some()) {
+            result = change_owner(path, uid, gid);
+        }
+        match result {
+            Ok(())     => *changed += 1,
+            Err(error) => errors.push(error),
+        }
+        if self.recursive && metadata.is_dir() {
+            match fs::read_dir(path) {
+                Ok(entries) => for entry in entries.filter_map(|entry| entry.ok()) {
+                    self.apply_to(&entry.path(), uid, gid, changed, errors);
+                },
+                Err(e) => errors.push(format!("Could not read {}: {}", path.display(), e)),
+            }
+        }
+    }
+}
+//-----------------------------------------------------------------------------
+fn change_owner(path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), String> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format!("Invalid path {}", path.display()))?;
+    // -1 leaves it as it is
+    let uid = uid.unwrap_or(u32::MAX);
+    let gid = gid.unwrap_or(u32::MAX);
+    if unsafe { libc::lchown(c_path.as_ptr(), uid, gid) } == 0 { return Ok(()); }
+    Err(format!("Could not change the owner of {}: {}", path.display(), std::io::Error::last_os_error()))
+}
+
+// Falls back to the number when there is no such user
+pub fn user_name(uid: u32) -> String {
+    let passwd = unsafe { libc::getpwuid(uid) };
+    if passwd.is_null() { return uid.to_string(); }
+    unsafe { CStr::from_ptr((*passwd).pw_name) }.to_string_lossy().into_owned()
+}
+
+pub fn group_name(gid: u32) -> String {
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() { return gid.to_string(); }
+    unsafe { CStr::from_ptr((*group).gr_name) }.to_string_lossy().into_owned()
+}
+
+// A number is taken as is
+fn user_id(name: &str) -> Result<u32, String> {
+    if let Ok(uid) = name.parse::<u32>() { return Ok(uid); }
+    let c_name = CString::new(name).map_err(|_| format!("Invalid user name `{}`", name))?;
+    let passwd = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if passwd.is_null() { return Err(format!("No such user: {}", name)); }
+    Ok(unsafe { (*passwd).pw_uid })
+}
+
+fn group_id(name: &str) -> Result<u32, String> {
+    if let Ok(gid) = name.parse::<u32>() { return Ok(gid); }
+    let c_name = CString::new(name).map_err(|_| format!("Invalid group name `{}`", name))?;
+    let group = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if group.is_null() { return Err(format!("No such group: {}", name)); }
+    Ok(unsafe { (*group).gr_gid })
+}
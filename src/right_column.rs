@@ -1,9 +1,9 @@
 use crate::direntry::*;
-use crate::input::*;
 use crate::coloring::*;
 use crate::utils::*;
 use crate::spawn::*;
 use crate::filesystem::*;
+use crate::matching::NameFilter;
 use std::path::PathBuf;
 
 #[derive(Clone)]
@@ -15,16 +15,17 @@ pub struct RightColumn {
 impl RightColumn {
     pub fn collect(path_opt: &Option<PathBuf>,
                    paint_settings: &PaintSettings,
-                   sorting_type: &SortingType,
+                   sorting_for: &dyn Fn(&PathBuf) -> Sorting,
+                   filter: Option<&NameFilter>,
                    include_hidden: bool,
-                   max_height: usize, max_width: usize,
+                   (max_height, max_width): (usize, usize),
                    selected: &Vec<PathBuf>) -> RightColumn {
         if let Some(path) = path_opt {
             if path.is_dir() { // resolved path
                 return RightColumn::with_siblings(
                     into_sorted_direntries(
-                        collect_maybe_dir(&path, Some(max_height), include_hidden),
-                        paint_settings, sorting_type, selected, Some(&path)));
+                        collect_maybe_dir(path, Some(max_height), include_hidden),
+                        paint_settings, &sorting_for(path), filter, selected, Some(path)));
             } else { // resolved path is a regular file
                 let path = maybe_resolve_symlink_recursively(path);
                 if let Some(preview) = read_preview_of(&path, max_height) {
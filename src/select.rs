@@ -0,0 +1,191 @@
+use crate::direntry::*;
+use crate::filesystem::*;
+use crate::matching::*;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+// A query is a list of criteria separated by spaces, an entry has to meet all of them
+//
+//     *.rs  /^img_\d+/i       the name, as a glob or a regex between slashes
+//     type=dir|file|exec|link
+//     size>100M  size<10K     units are B, K, M, G and T, counting by 1024
+//     age<2d     age>1w       since the last modification; s, m, h, d and w
+//-----------------------------------------------------------------------------
+pub enum Criterion {
+    Name(NameFilter),
+    Type(KindOfEntry),
+    LargerThan(u64),
+    SmallerThan(u64),
+    NewerThan(u64), // seconds ago
+    OlderThan(u64),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KindOfEntry {
+    Directory,
+    File,
+    Executable,
+    Symlink,
+}
+
+pub fn select_prompt(select: bool) -> &'static str {
+    if select { "select: " } else { "deselect: " }
+}
+
+pub fn parse_selection_query(text: &str) -> Result<Vec<Criterion>, String> {
+    let criteria = text.split_whitespace().map(parse_criterion).collect::<Result<Vec<_>, String>>()?;
+    if criteria.is_empty() { return Err("Empty query".to_string()); }
+    Ok(criteria)
+}
+
+fn parse_criterion(word: &str) -> Result<Criterion, String> {
+    if let Some(kind) = word.strip_prefix("type=") {
+        match kind {
+            "dir"  => Ok(Criterion::Type(KindOfEntry::Directory)),
+            "file" => Ok(Criterion::Type(KindOfEntry::File)),
+            "exec" => Ok(Criterion::Type(KindOfEntry::Executable)),
+            "link" => Ok(Criterion::Type(KindOfEntry::Symlink)),
+            other  => Err(format!("Unknown type `{}` (expected dir, file, exec or link)", other)),
+        }
+    } else if let Some(amount) = word.strip_prefix("size>") {
+        parse_amount(amount, &SIZE_UNITS).map(Criterion::LargerThan)
+    } else if let Some(amount) = word.strip_prefix("size<") {
+        parse_amount(amount, &SIZE_UNITS).map(Criterion::SmallerThan)
+    } else if let Some(amount) = word.strip_prefix("age<") {
+        parse_amount(amount, &AGE_UNITS).map(Criterion::NewerThan)
+    } else if let Some(amount) = word.strip_prefix("age>") {
+        parse_amount(amount, &AGE_UNITS).map(Criterion::OlderThan)
+    } else {
+        parse_name_filter(word).map(Criterion::Name)
+    }
+}
+
+const SIZE_UNITS: [(char, u64); 5] = [
+    ('B', 1), ('K', 1 << 10), ('M', 1 << 20), ('G', 1 << 30), ('T', 1 << 40),
+];
+const AGE_UNITS: [(char, u64); 5] = [
+    ('s', 1), ('m', 60), ('h', 60 * 60), ('d', 24 * 60 * 60), ('w', 7 * 24 * 60 * 60),
+];
+
+// E.g. "100M" or "2d"; a number alone counts in the first unit
+fn parse_amount(text: &str, units: &[(char, u64)]) -> Result<u64, String> {
+    let (number, multiplier) = match text.chars().last() {
+        Some(last) if !last.is_ascii_digit() => {
+            let multiplier = units.iter().find(|(unit, _)| *unit == last).map(|(_, m)| *m)
+                .ok_or_else(|| format!("Unknown unit `{}` in `{}`", last, text))?;
+            (&text[..text.len() - last.len_utf8()], multiplier)
+        },
+        _ => (text, 1),
+    };
+    number.parse::<u64>().map(|number| number.saturating_mul(multiplier))
+        .map_err(|_| format!("`{}` is not an amount", text))
+}
+
+pub fn meets_all(criteria: &[Criterion], entry: &DirEntry, path: &PathBuf) -> bool {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let age = now.saturating_sub(entry.time_modified);
+    criteria.iter().all(|criterion| match criterion {
+        Criterion::Name(filter)      => filter.is_match(&entry.name),
+        Criterion::Type(kind)        => is_of_kind(entry, path, *kind),
+        Criterion::LargerThan(size)  => entry.size > *size,
+        Criterion::SmallerThan(size) => entry.size < *size,
+        Criterion::NewerThan(secs)   => age < *secs,
+        Criterion::OlderThan(secs)   => age > *secs,
+    })
+}
+
+// Executables are files too
+fn is_of_kind(entry: &DirEntry, path: &PathBuf, kind: KindOfEntry) -> bool {
+    match kind {
+        KindOfEntry::Directory  => entry.entrytype == EntryType::Directory,
+        KindOfEntry::Symlink    => entry.entrytype == EntryType::Symlink,
+        KindOfEntry::File       => entry.entrytype == EntryType::Regular,
+        KindOfEntry::Executable => entry.entrytype == EntryType::Regular &&
+            fs::symlink_metadata(path).map(|meta| meta.permissions().mode() & 0o111 != 0).unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_of(text: &str) -> String {
+        match parse_selection_query(text) {
+            Ok(_)      => panic!("`{}` should not parse", text),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn parse_amount_applies_the_units() {
+        assert_eq!(parse_amount("100", &SIZE_UNITS), Ok(100));
+        assert_eq!(parse_amount("3B", &SIZE_UNITS), Ok(3));
+        assert_eq!(parse_amount("2K", &SIZE_UNITS), Ok(2048));
+        assert_eq!(parse_amount("10M", &SIZE_UNITS), Ok(10 << 20));
+        assert_eq!(parse_amount("1T", &SIZE_UNITS), Ok(1 << 40));
+        assert_eq!(parse_amount("90", &AGE_UNITS), Ok(90));
+        assert_eq!(parse_amount("2h", &AGE_UNITS), Ok(2 * 60 * 60));
+        assert_eq!(parse_amount("1w", &AGE_UNITS), Ok(7 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn parse_amount_rejects_what_is_not_an_amount() {
+        assert_eq!(parse_amount("", &SIZE_UNITS), Err("`` is not an amount".to_string()));
+        assert_eq!(parse_amount("M", &SIZE_UNITS), Err("`M` is not an amount".to_string()));
+        assert_eq!(parse_amount("-1K", &SIZE_UNITS), Err("`-1K` is not an amount".to_string()));
+        assert_eq!(parse_amount("1.5G", &SIZE_UNITS), Err("`1.5G` is not an amount".to_string()));
+    }
+
+    #[test]
+    fn parse_amount_rejects_unknown_units() {
+        assert_eq!(parse_amount("10X", &SIZE_UNITS), Err("Unknown unit `X` in `10X`".to_string()));
+        assert_eq!(parse_amount("2d", &SIZE_UNITS), Err("Unknown unit `d` in `2d`".to_string()));
+        assert_eq!(parse_amount("2K", &AGE_UNITS), Err("Unknown unit `K` in `2K`".to_string()));
+    }
+
+    #[test]
+    fn parse_amount_handles_multibyte_units() {
+        assert_eq!(parse_amount("5ä", &SIZE_UNITS), Err("Unknown unit `ä` in `5ä`".to_string()));
+        assert_eq!(parse_amount("ä", &AGE_UNITS), Err("Unknown unit `ä` in `ä`".to_string()));
+        assert_eq!(parse_amount("5€M", &SIZE_UNITS), Err("`5€M` is not an amount".to_string()));
+    }
+
+    #[test]
+    fn parse_amount_saturates() {
+        assert_eq!(parse_amount("99999999999T", &SIZE_UNITS), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn parse_selection_query_reads_every_criterion() {
+        let criteria = parse_selection_query("*.rs  type=exec size>1K size<2M age<2d age>3h").unwrap();
+        assert_eq!(criteria.len(), 6);
+        assert!(matches!(&criteria[0], Criterion::Name(filter) if filter.is_match("main.rs")));
+        assert!(matches!(criteria[1], Criterion::Type(KindOfEntry::Executable)));
+        assert!(matches!(criteria[2], Criterion::LargerThan(1024)));
+        assert!(matches!(criteria[3], Criterion::SmallerThan(size) if size == 2 << 20));
+        assert!(matches!(criteria[4], Criterion::NewerThan(secs) if secs == 2 * 24 * 60 * 60));
+        assert!(matches!(criteria[5], Criterion::OlderThan(secs) if secs == 3 * 60 * 60));
+    }
+
+    #[test]
+    fn parse_selection_query_rejects_bad_queries() {
+        assert_eq!(error_of(""), "Empty query");
+        assert_eq!(error_of("   "), "Empty query");
+        assert_eq!(error_of("size>"), "`` is not an amount");
+        assert_eq!(error_of("age<"), "`` is not an amount");
+        assert_eq!(error_of("*.rs size>10Q"), "Unknown unit `Q` in `10Q`");
+        assert_eq!(error_of("type=pipe"), "Unknown type `pipe` (expected dir, file, exec or link)");
+        assert!(error_of("/(/").starts_with("Invalid filter"));
+    }
+
+    #[test]
+    fn parse_selection_query_takes_multibyte_names() {
+        let criteria = parse_selection_query("größe*.txt").unwrap();
+        assert!(match &criteria[0] { Criterion::Name(filter) => filter.is_match("größe_1.txt"), _ => false });
+        assert_eq!(error_of("size>1ö"), "Unknown unit `ö` in `1ö`");
+    }
+}
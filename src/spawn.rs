@@ -1,8 +1,7 @@
-use std::process::Child;
 use std::process::{Command, Stdio};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 
 #[derive(Clone)]
 pub struct SpawnRule {
@@ -140,16 +139,46 @@ pub fn execute_command_from(path: &PathBuf, command: &str) {
         .spawn().expect("failed to execute process");
 }
 
-pub fn spawn_process_async<S: AsRef<OsStr>>(app: &str, args: Vec<S>) -> Child {
-    Command::new(app).args(args)
-        .stderr(Stdio::null()).stdout(Stdio::piped())
-        .spawn().expect("failed to execute process")
+// $VISUAL or $EDITOR, vi if neither is set
+fn editor() -> String {
+    ["VISUAL", "EDITOR"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or("vi".to_string())
 }
 
-pub fn spawn_process_wait<S: AsRef<OsStr>>(app: &str, args: Vec<S>) {
-    Command::new(app).args(args)
-        .stderr(Stdio::null()).stdout(Stdio::null())
-        .status().expect("failed to execute process");
+// Waits for the editor to finish editing the file
+pub fn edit_file(file: &PathBuf) -> Result<(), String> {
+    let editor = editor();
+    let mut parts = editor.split_whitespace();
+    let app = parts.next().unwrap(); // not empty
+    let status = Command::new(app).args(parts).arg(file).status()
+        .map_err(|e| format!("Could not run {}: {}", app, e))?;
+    if status.success() { Ok(()) }
+    else                { Err(format!("{} has exited with {}", app, status)) }
+}
+
+// Opens the file in the terminal editor of its rule, or else in the editor, with
+// `+line` before the file, which vi, vim, nano, emacs and the like understand
+pub fn edit_file_at_line(file: &PathBuf, line: usize, spawn_patterns: &Vec<SpawnPattern>)
+        -> Result<(), String> {
+    // The rules only know the names that are valid UTF-8
+    let rule = file.to_str().and_then(|_| spawn_rule_for(file, spawn_patterns));
+    let (app, mut args): (String, Vec<OsString>) = match rule {
+        Some((app, args, false)) => (app, args.into_iter().map(OsString::from).collect()),
+        _ => {
+            let editor = editor();
+            let mut parts = editor.split_whitespace().map(|part| part.to_string());
+            let app = parts.next().unwrap(); // not empty
+            (app, parts.map(OsString::from).chain(vec![file.as_os_str().to_os_string()]).collect())
+        },
+    };
+    let position = args.iter().position(|arg| arg == file.as_os_str()).unwrap_or(args.len());
+    args.insert(position, OsString::from(format!("+{}", line)));
+    let status = Command::new(&app).args(args).status()
+        .map_err(|e| format!("Could not run {}: {}", app, e))?;
+    if status.success() { Ok(()) }
+    else                { Err(format!("{} has exited with {}", app, status)) }
 }
 
 pub fn spawn_program<S: AsRef<OsStr>>(app: &str, args: Vec<S>, is_external: bool) {
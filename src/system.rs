@@ -1,8 +1,8 @@
 use pancurses::{Window, initscr, start_color, use_default_colors, noecho,
     half_delay, endwin, curs_set, nocbreak};
-use std::path::PathBuf;
-use std::collections::{HashSet};
-// use std::collections::{HashMap};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::collections::{HashSet, HashMap};
 
 use crate::coloring::*;
 use crate::utils::*;
@@ -16,6 +16,20 @@ use crate::drawing::*;
 use crate::context::*;
 use crate::tab::*;
 use crate::notification::*;
+use crate::bookmarks::*;
+use crate::cli::ExitState;
+use crate::transfer::*;
+use crate::trash::*;
+use crate::journal::*;
+use crate::bulk_rename::*;
+use crate::pattern_rename::*;
+use crate::create::*;
+use crate::permissions::*;
+use crate::details::*;
+use crate::find::*;
+use crate::grep::*;
+use crate::matching::*;
+use crate::select::*;
 //-----------------------------------------------------------------------------
 pub struct Settings {
     pub paint_settings: PaintSettings,
@@ -25,6 +39,20 @@ pub struct Settings {
     pub columns_ratio: Vec<u32>,
     pub scrolling_gap: usize,
     pub copy_done_notification_delay_ms: Millis,
+
+    pub show_hidden: bool,
+    pub sorting: Sorting,
+    pub sort_per_directory: bool, // otherwise per tab
+    pub conflict_policy: ConflictPolicy,
+
+    pub confirm_trash: bool,
+    pub confirm_delete: bool,
+    pub confirm_move: bool,
+    pub confirm_rename: bool,
+
+    pub status_fields: Vec<StatusField>,
+    pub find_ignore: Vec<String>, // names that the find does not descend into
+    pub search_mode: SearchMode,  // the one a new search starts with
 }
 
 //-----------------------------------------------------------------------------
@@ -33,13 +61,19 @@ pub struct System {
     settings: Settings,
     renderer: Renderer,
 
-    sorting_type: SortingType,
+    directory_sortings: HashMap<PathBuf, Sorting>, // for `sort_per_directory`
     spawn_patterns: Vec<SpawnPattern>, // const
 
     notification: Option<Notification>,
+    pending_keys: String, // the count and the combination typed so far
 
     transfers: Vec<Transfer>,
     potential_transfer_data: Option<PotentialTransfer>,
+    pending_paste: Option<PendingPaste>,
+    find: Option<Find>, // for the current tab, the walk stops when leaving it
+    grep: Option<Grep>, // likewise
+    search_mode: SearchMode, // the last one used
+    visual_anchor: Option<usize>, // index among the current siblings, while in visual mode
 
     selected: Vec<PathBuf>,
 
@@ -47,21 +81,28 @@ pub struct System {
     current_tab_index: usize,
 
     show_hidden: bool,
+
+    bookmarks: Bookmarks,
+    journal: Journal,
+    show_transfers: bool,
+    show_details: bool,
 }
 
 impl System {
-    pub fn new(settings: Settings, starting_path: PathBuf) -> Self {
+    pub fn new(settings: Settings, bookmarks: Bookmarks, journal: Journal,
+               starting_path: PathBuf) -> Self {
         let window = System::setup();
         System::set_drawing_delay(DrawingDelay::Regular);
 
-        let show_hidden = true; // TODO: move into Settings
+        let show_hidden = settings.show_hidden;
         let selected = Vec::new();
-        let sorting_type = SortingType::Lexicographically;
+        let sorting = settings.sorting;
+        let search_mode = settings.search_mode;
         let display_settings = DisplaySettings::generate(
             &window, settings.scrolling_gap, &settings.columns_ratio);
         let context = Context::generate(starting_path, &display_settings,
-                               &settings.paint_settings, &sorting_type,
-                               show_hidden, &selected);
+                               &settings.paint_settings, &|_| sorting,
+                               None, show_hidden, &selected);
 
         System {
             // window,
@@ -69,25 +110,62 @@ impl System {
             renderer: Renderer::new(window, display_settings),
             // display_settings,
 
-            sorting_type,
+            directory_sortings: HashMap::new(),
             spawn_patterns: generate_spawn_patterns(),
 
             notification: None,      // for Transfers
+            pending_keys: String::new(),
 
             transfers: Vec::new(),
             potential_transfer_data: None,
+            pending_paste: None,
+            find: None,
+            grep: None,
+            search_mode,
+            visual_anchor: None,
 
             selected,
 
-            tabs: vec![Tab { name: tab_name_from_path(&context.parent_path), context }],
+            tabs: vec![Tab { name: tab_name_from_path(&context.parent_path), context, sorting, filter: None }],
             current_tab_index: 0,
             show_hidden,
+
+            bookmarks,
+            journal,
+            show_transfers: false,
+            show_details: false,
         }
     }
+
+    fn notify(&mut self, text: &str) {
+        const NOTIFICATION_DELAY_MS: Millis = 3000;
+        self.notification = Some(Notification::new(text, NOTIFICATION_DELAY_MS));
+    }
+
+    pub fn report_errors(&mut self, errors: &[String]) {
+        if errors.is_empty() { return; }
+        const ERROR_NOTIFICATION_DELAY_MS: Millis = 10000;
+        let text = if errors.len() == 1 { errors[0].clone() }
+                   else { format!("{} (and {} more)", errors[0], errors.len() - 1) };
+        self.notification = Some(Notification::new(&text, ERROR_NOTIFICATION_DELAY_MS));
+    }
 //-----------------------------------------------------------------------------
     fn generate_context_for(&mut self, parent_path: PathBuf) -> Context {
         Context::generate(parent_path, &self.renderer.display_settings,
-            &self.settings.paint_settings, &self.sorting_type, self.show_hidden, &self.selected)
+            &self.settings.paint_settings, &|path| self.sorting_for(path),
+            self.current_tab_ref().filter.as_ref(), self.show_hidden, &self.selected)
+    }
+
+    // The one remembered for the directory, if any, or else the one of the tab
+    fn sorting_for(&self, dir: &PathBuf) -> Sorting {
+        if self.settings.sort_per_directory {
+            if let Some(sorting) = self.directory_sortings.get(dir) { return *sorting; }
+        }
+        self.current_tab_ref().sorting
+    }
+
+    fn current_sorting(&self) -> Sorting {
+        self.sorting_for(&self.context_ref().parent_path)
     }
 
 //-----------------------------------------------------------------------------
@@ -98,109 +176,407 @@ impl System {
         let column_width = (end - begin) as usize;
         let current_path = &self.context_ref().current_path;
         RightColumn::collect(current_path, &self.settings.paint_settings,
-                             &self.sorting_type, self.show_hidden,
-                             self.renderer.display_settings.column_effective_height,
-                             column_width, &self.selected)
+                             &|path| self.sorting_for(path), self.current_tab_ref().filter.as_ref(),
+                             self.show_hidden,
+                             (self.renderer.display_settings.column_effective_height, column_width),
+                             &self.selected)
     }
 
 //-----------------------------------------------------------------------------
 
 //-----------------------------------------------------------------------------
-    fn current_contains(&self, name: &str) -> bool {
-        for entry in self.context_ref().current_siblings.iter() {
-            if entry.name == name { return true; }
+    pub fn paste_into_current(&mut self) {
+        let question = match self.potential_transfer_data.as_ref() {
+            Some(potential) if potential.transfer_type == TransferType::Cut
+                    && self.settings.confirm_move => format!("Move {} into {}?",
+                System::describe(&potential.src_paths),
+                path_to_str(&self.context_ref().parent_path)),
+            Some(_) => return self.start_paste(),
+            None    => return,
+        };
+        self.ask(question, ConfirmedAction::Paste);
+    }
+
+    // The conflicting items get resolved according to the settings or by asking
+    fn start_paste(&mut self) {
+        if let Some(potential) = self.potential_transfer_data.take() {
+            let items = potential.items_into(&self.context_ref().parent_path);
+            let (conflicts, resolved) = items.into_iter().partition(|item| exists(&item.dst));
+            self.pending_paste = Some(PendingPaste { potential, resolved, conflicts });
+            let policy = self.settings.conflict_policy;
+            if policy != ConflictPolicy::Ask { self.resolve_conflicts_with(policy, true); }
+            self.maybe_finish_paste();
         }
-        false
     }
 
-    fn cut(src: &str, dst: &str) {
-        spawn_process_async("mv", vec![src, dst]);
+    pub fn resolving_conflict(&self) -> bool {
+        self.pending_paste.is_some()
     }
 
-    fn yank(src: &str, dst: &str) {
-        spawn_process_async("cp", vec!["-a", src, dst]);
-        // Don't use rsync because it generates modified target names which makes
-        // it impossible to track target's size until the transfer has finished.
-        // System::spawn_process_async("rsync", vec!["-a", "-v", "-h", src, dst]);
+    // A lowercase key resolves the current conflict, an uppercase one all the remaining ones
+    pub fn resolve_conflict_with_key(&mut self, key: char) {
+        let policy = match key.to_ascii_lowercase() {
+            'o' => ConflictPolicy::Overwrite,
+            's' => ConflictPolicy::Skip,
+            'r' => ConflictPolicy::Rename,
+            'n' => ConflictPolicy::KeepNewer,
+            'm' => ConflictPolicy::Merge,
+            _   => return,
+        };
+        if policy == ConflictPolicy::Merge && !key.is_uppercase() {
+            let item = &self.pending_paste.as_ref().unwrap().conflicts[0];
+            if !(is_dir(&item.src) && is_dir(&item.dst)) { return; } // only directories merge
+        }
+        self.resolve_conflicts_with(policy, key.is_uppercase());
+        self.maybe_finish_paste();
     }
 
-    pub fn paste_into_current(&mut self) {
-        if let Some(data) = self.potential_transfer_data.as_ref() {
-            let dst_paths: Vec<PathBuf> = data.src_paths.iter()
-                .map(|src_path| {
-                    let mut dst_name = file_name(src_path);
-                    while self.current_contains(&dst_name) { dst_name += "_"; }
-                    self.context_ref().parent_path.join(dst_name)
-                }).collect();
-            for (src_path, dst_path) in data.src_paths.iter().zip(dst_paths.iter()) {
-                let mut src = path_to_string(src_path);
-                match data.transfer_type {
-                    TransferType::Cut  => if is_dir(src_path) { src += "/"; },
-                    TransferType::Yank => if is_dir(src_path) { src += "/."; },
-                }
+    // Puts the data back, so that the paste may be retried
+    pub fn cancel_paste(&mut self) {
+        if let Some(pending) = self.pending_paste.take() {
+            self.potential_transfer_data = Some(pending.potential);
+        }
+    }
 
-                let dst = path_to_string(dst_path);
-                match data.transfer_type {
-                    TransferType::Cut  => System::cut(&src, &dst),
-                    TransferType::Yank => System::yank(&src, &dst),
+    fn resolve_conflicts_with(&mut self, policy: ConflictPolicy, all: bool) {
+        if let Some(pending) = self.pending_paste.as_mut() {
+            let amount = if all { pending.conflicts.len() } else { 1 };
+            let transfer_type = pending.potential.transfer_type;
+            for item in pending.conflicts.drain(..amount) {
+                if let Some(item) = resolve_conflict(item, policy, transfer_type) {
+                    pending.resolved.push(item);
                 }
             }
-            self.transfers.push(self.potential_transfer_data.take().unwrap().with_dst_paths(dst_paths));
-            System::set_drawing_delay(DrawingDelay::Transfering);
-            self.update_current();
         }
     }
 
+    fn maybe_finish_paste(&mut self) {
+        if self.pending_paste.as_ref().is_none_or(|pending| !pending.conflicts.is_empty()) { return; }
+        let PendingPaste { potential, resolved, .. } = self.pending_paste.take().unwrap();
+        if resolved.is_empty() {
+            self.notify("Nothing to paste");
+            return;
+        }
+        self.transfers.push(potential.start(resolved));
+        System::set_drawing_delay(DrawingDelay::Transfering);
+        self.update_current();
+    }
+
     // TODO: mb merge with cut_selected
-    pub fn yank_selected(&mut self) {
-        if self.selected.is_empty() {
-            if let Some(path) = self.context_ref().current_path.as_ref() {
-                self.potential_transfer_data = Some(PotentialTransfer::yank(vec![path.clone()]));
+    pub fn yank_selected(&mut self, count: usize) {
+        let paths = self.selected_or_following(count);
+        if paths.is_empty() { return; }
+        self.selected.clear();
+        self.potential_transfer_data = Some(PotentialTransfer::yank(paths));
+    }
+
+    // TODO: mb merge with yank_selected
+    pub fn cut_selected(&mut self, count: usize) {
+        let paths = self.selected_or_following(count);
+        if paths.is_empty() { return; }
+        self.selected.clear();
+        self.potential_transfer_data = Some(PotentialTransfer::cut(paths));
+    }
+
+    fn selected_or_current(&self) -> Vec<PathBuf> {
+        self.selected_or_following(1)
+    }
+
+    // Without a selection, the entry under the cursor and the ones after it, `count` in all
+    fn selected_or_following(&self, count: usize) -> Vec<PathBuf> {
+        if !self.selected.is_empty() { return self.selected.clone(); }
+        let current_path = match self.context_ref().current_path.as_ref() {
+            Some(path) => path.clone(),
+            None       => return Vec::new(),
+        };
+        if count <= 1 || self.doing_grep() { return vec![current_path]; }
+        let parent_path = &self.context_ref().parent_path;
+        self.context_ref().current_siblings.iter()
+            .skip(self.context_ref().current_index).take(count)
+            .map(|entry| parent_path.join(&entry.name)).collect()
+    }
+
+    // E.g. "notes.txt (4 B)", "src" or "14 items (2.3 G, not counting directories)".
+    // The directories are not summed up, that could take long
+    fn describe(paths: &[PathBuf]) -> String {
+        let mut size = 0;
+        let mut directories = 0;
+        for path in paths.iter() {
+            match path.symlink_metadata() {
+                Ok(ref meta) if meta.is_dir() => directories += 1,
+                Ok(meta)                      => size += meta.len(),
+                Err(_)                        => {},
             }
-        } else {
-            let selected = self.selected.drain(..).collect();
-            self.potential_transfer_data = Some(PotentialTransfer::yank(selected));
         }
+        let what = if paths.len() == 1 { file_name(&paths[0]) }
+                   else                { format!("{} items", paths.len()) };
+        if directories == paths.len() { what }
+        else if directories == 0      { format!("{} ({})", what, human_size(size)) }
+        else                          { format!("{} ({}, not counting directories)", what, human_size(size)) }
     }
 
-    // TODO: mb merge with yank_selected
-    pub fn cut_selected(&mut self) {
-        if self.selected.is_empty() {
-            if let Some(path) = self.context_ref().current_path.as_ref() {
-                self.potential_transfer_data = Some(PotentialTransfer::cut(vec![path.clone()]));
+    // The selection is global, so it may include entries that are not on screen
+    fn elsewhere_warning(&self, paths: &[PathBuf]) -> String {
+        let parent_path = self.context_ref().parent_path.as_path();
+        let elsewhere: Vec<&PathBuf> = paths.iter()
+            .filter(|path| path.parent() != Some(parent_path)).collect();
+        if elsewhere.is_empty()  { String::new() }
+        else if paths.len() == 1 { format!(" (it is in {})", elsewhere[0].parent().unwrap().display()) }
+        else                     { format!(" ({} of them outside of this directory)", elsewhere.len()) }
+    }
+
+    fn ask(&mut self, question: String, action: ConfirmedAction) {
+        self.reset_input_mode_and_restore(); // an active search would get lost otherwise
+        self.update_current_without_siblings();
+        self.context_mut().input_mode = Some(InputMode::Confirm(ConfirmTools { question, action }));
+    }
+
+    fn answer_confirmation(&mut self, yes: bool) {
+        if let Some(InputMode::Confirm(ConfirmTools { action, .. })) = self.context_mut().input_mode.take() {
+            if !yes { return; }
+            match action {
+                ConfirmedAction::Trash(paths)  => self.trash(paths),
+                ConfirmedAction::Delete(paths) => self.delete(paths),
+                ConfirmedAction::Paste         => self.start_paste(),
+                ConfirmedAction::Rename(path, new_name) => {
+                    self.rename(&path, &new_name);
+                    self.update_current();
+                },
+                ConfirmedAction::BulkRename(renames) => self.apply_renames(renames),
             }
-        } else {
-            let selected = self.selected.drain(..).collect();
-            self.potential_transfer_data = Some(PotentialTransfer::cut(selected));
         }
     }
 
-    pub fn remove_selected(&mut self) {
-        if self.selected.is_empty() {
-            if let Some(path) = self.context_ref().current_path.as_ref() {
-                System::remove(path);
+    // Applies the action to every path, reports the failures and what has been done.
+    // The operations that can be undone get recorded together.
+    fn apply_to<F>(&mut self, paths: Vec<PathBuf>, done: &str, action: F)
+            where F: Fn(&PathBuf) -> Result<Option<Operation>, String> {
+        self.selected.clear(); // the paths are either the selection or the current entry
+        let mut errors = Vec::new();
+        let mut succeeded = Vec::new();
+        let mut batch = Vec::new();
+        for path in paths.into_iter() {
+            match action(&path) {
+                Ok(operation) => { batch.extend(operation); succeeded.push(path); },
+                Err(error)    => errors.push(error),
             }
+        }
+        self.record(batch);
+        if !errors.is_empty() {
+            self.report_errors(&errors);
+        } else if succeeded.len() == 1 {
+            self.notify(&done.replace("{}", &file_name(&succeeded[0])));
+        } else if succeeded.len() > 1 {
+            self.notify(&done.replace("{}", &format!("{} entries", succeeded.len())));
+        }
+        self.update_current();
+    }
+
+    pub fn trash_selected(&mut self, count: usize) {
+        let paths = self.selected_or_following(count);
+        if paths.is_empty() { return; }
+        if paths.iter().all(|path| is_trashed(path)) {
+            return self.delete_selected(count);
+        }
+        if self.settings.confirm_trash {
+            let question = format!("Move {} to the trash?{}{}", System::describe(&paths),
+                                   System::already_trashed_warning(&paths), self.elsewhere_warning(&paths));
+            self.ask(question, ConfirmedAction::Trash(paths));
         } else {
-            for item in self.selected.drain(..) {
-                System::remove(&item);
+            self.trash(paths);
+        }
+    }
+
+    // There is no trash for the trash itself, so the entries in it are deleted for good
+    fn already_trashed_warning(paths: &[PathBuf]) -> String {
+        match paths.iter().filter(|path| is_trashed(path)).count() {
+            0      => String::new(),
+            1      => " 1 entry is in the trash already and will be deleted permanently.".to_string(),
+            amount => format!(" {} entries are in the trash already and will be deleted permanently.", amount),
+        }
+    }
+
+    fn trash(&mut self, paths: Vec<PathBuf>) {
+        let deleted = paths.iter().filter(|path| is_trashed(path)).count();
+        let trashed = paths.len() - deleted;
+        let done = match (trashed, deleted) {
+            (_, 0) => "Moved {} to the trash".to_string(),
+            (0, _) => "Deleted {} permanently".to_string(),
+            _      => format!("Moved {} {} to the trash and deleted {} permanently",
+                              trashed, if trashed == 1 { "entry" } else { "entries" }, deleted),
+        };
+        self.apply_to(paths, &done, |path| {
+            if is_trashed(path) { return delete_permanently(path).map(|_| None); }
+            let trashed = move_to_trash(path)?;
+            Ok(Some(Operation::Trash { from: path.clone(), to: trashed }))
+        });
+    }
+
+    pub fn delete_selected(&mut self, count: usize) {
+        let paths = self.selected_or_following(count);
+        if paths.is_empty() { return; }
+        if self.settings.confirm_delete {
+            let question = format!("Delete {} permanently?{}",
+                                   System::describe(&paths), self.elsewhere_warning(&paths));
+            self.ask(question, ConfirmedAction::Delete(paths));
+        } else {
+            self.delete(paths);
+        }
+    }
+
+    fn delete(&mut self, paths: Vec<PathBuf>) {
+        self.apply_to(paths, "Deleted {} permanently", |path| delete_permanently(path).map(|_| None));
+    }
+
+    pub fn restore_selected(&mut self) {
+        let paths = self.selected_or_current();
+        self.apply_to(paths, "Restored {}", |path| {
+            if !is_trashed(path) { return Err(format!("{} is not in the trash", path.display())); }
+            let restored = restore(path)?;
+            Ok(Some(Operation::Restore { from: path.clone(), to: restored }))
+        });
+    }
+
+    pub fn show_trash(&mut self) {
+        match trash_files_dir() {
+            Some(dir) if dir.is_dir() => self.goto_path(dir),
+            Some(_) => self.notify("The trash is empty"),
+            None    => self.notify("Could not locate the trash directory"),
+        }
+    }
+
+    fn rename(&mut self, path: &PathBuf, new_name: &str) {
+        let new_path = path.parent().unwrap().join(new_name);
+        if new_path == *path { return; }
+        match fs::rename(path, &new_path) {
+            Ok(()) => self.record(vec![Operation::Move { from: path.clone(), to: new_path }]),
+            Err(e) => self.notify(&format!("Could not rename {}: {}", path.display(), e)),
+        }
+    }
+
+    // Renames the selection, or everything in the directory if nothing is selected
+    pub fn bulk_rename(&mut self) {
+        let parent_path = self.context_ref().parent_path.clone();
+        let paths: Vec<PathBuf> = if self.selected.is_empty() {
+            self.context_ref().current_siblings.iter()
+                .map(|entry| parent_path.join(&entry.name)).collect()
+        } else {
+            self.selected.clone()
+        };
+        if paths.is_empty() { return; }
+        match self.edit_names(&paths, &parent_path) {
+            Ok(ref renames) if renames.is_empty() => self.notify("Nothing to rename"),
+            Ok(renames) => {
+                let question = format!("Rename {} {}?", renames.len(),
+                                       if renames.len() == 1 { "entry" } else { "entries" });
+                self.ask(question, ConfirmedAction::BulkRename(renames));
+            },
+            Err(error) => self.notify(&error),
+        }
+    }
+
+    fn edit_names(&mut self, paths: &[PathBuf], parent_path: &Path) -> Result<Vec<Rename>, String> {
+        let file = write_names(paths, parent_path)?;
+        let edited = edit_file(&file).and_then(|_| fs::read_to_string(&file)
+            .map_err(|e| format!("Could not read {}: {}", file.display(), e)));
+        let _ = fs::remove_file(&file);
+        self.renderer.invalidate(); // Otherwise the screen is not restored correctly
+        plan_renames(paths, parent_path, &edited?)
+    }
+
+    // Stops at the first failure, what has been done so far may be undone
+    fn apply_renames(&mut self, renames: Vec<Rename>) {
+        let amount = renames.len();
+        let mut batch = Vec::new();
+        let mut error = None;
+        for (from, to) in order_renames(renames) {
+            match fs::rename(&from, &to) {
+                Ok(()) => batch.push(Operation::Move { from, to }),
+                Err(e) => {
+                    error = Some(format!("Could not rename {} to {}: {}", from.display(), to.display(), e));
+                    break;
+                },
             }
         }
+        self.record(batch);
+        self.selected.clear(); // the selected paths do not exist anymore
+        match error {
+            Some(error) => self.notify(&error),
+            None        => self.notify(&format!("Renamed {} {}", amount,
+                                                if amount == 1 { "entry" } else { "entries" })),
+        }
+        self.update_current();
+    }
+
+    // Lands on what has been created, even if it is not in this directory
+    fn create(&mut self, kind: &CreationKind, text: &str) {
+        let parent_path = self.context_ref().parent_path.clone();
+        let (created, error) = create(kind, &parent_path, text);
+        self.record(created.iter().map(|path| Operation::Create { path: path.clone() }).collect());
+        match kind {
+            CreationKind::Symlink { .. } | CreationKind::HardLink(_) => self.selected.clear(),
+            _ => {},
+        }
+        match error {
+            Some(error) => self.notify(&error),
+            None if created.len() == 1 => self.notify(&format!("Created {}", file_name(&created[0]))),
+            None => self.notify(&format!("Created {} links", created.len())),
+        }
         self.update_current();
+        if let Some(path) = created.first() {
+            let dir = path.parent().unwrap().to_path_buf();
+            if dir != parent_path { self.goto_path(dir); }
+            self.move_cursor_to(&file_name(path));
+        }
     }
 
-    fn remove(path: &PathBuf) {
-        if path.is_dir() {
-            spawn_process_wait("rm", vec!["-r", "-f", path_to_str(path)]);
-        } else if path.is_file() {
-            spawn_process_wait("rm", vec!["-f", path_to_str(path)]);
-        } else { // is symlink
-            spawn_process_wait("unlink", vec![path_to_str(path)]);
+    // The selected entries of this directory in the displayed order, so that the
+    // counter follows what is on screen, then the selected ones from elsewhere
+    fn pattern_rename_targets(&self) -> Vec<PathBuf> {
+        let parent_path = &self.context_ref().parent_path;
+        let siblings: Vec<PathBuf> = self.context_ref().current_siblings.iter()
+            .map(|entry| parent_path.join(&entry.name)).collect();
+        if self.selected.is_empty() { return siblings; }
+        let mut elsewhere: Vec<PathBuf> = self.selected.iter()
+            .filter(|path| !siblings.contains(path)).cloned().collect();
+        elsewhere.sort();
+        siblings.into_iter().filter(|path| self.selected.contains(path)).chain(elsewhere).collect()
+    }
+
+    fn planned_pattern_renames(&self, pattern: &str) -> Result<Vec<Rename>, String> {
+        let pattern = parse_pattern(pattern)?;
+        let mut renames = Vec::new();
+        for (index, path) in self.pattern_rename_targets().into_iter().enumerate() {
+            let name = file_name(&path);
+            let new_name = apply_pattern(&pattern, &name, index + 1);
+            if new_name == name { continue; }
+            if new_name.is_empty() || new_name.contains('/') || new_name == "." || new_name == ".." {
+                return Err(format!("{} would get the invalid name `{}`", name, new_name));
+            }
+            let new_path = path.parent().unwrap().join(new_name);
+            renames.push((path, new_path));
         }
+        Ok(renames)
     }
 
-    fn rename(path: &PathBuf, new_name: &str) {
-        let new_path = path.parent().unwrap().join(new_name);
-        spawn_process_wait("mv", vec![path_to_str(path), path_to_str(&new_path)]);
+    fn record(&mut self, batch: Batch) {
+        if let Err(error) = self.journal.record(batch) { self.notify(&error); }
+    }
+
+    pub fn undo(&mut self) {
+        match self.journal.undo() {
+            Ok(description) => self.notify(&format!("Undid {}", description)),
+            Err(error)      => self.notify(&error),
+        }
+        self.update_current();
+    }
+
+    pub fn redo(&mut self) {
+        match self.journal.redo() {
+            Ok(description) => self.notify(&format!("Redid {}", description)),
+            Err(error)      => self.notify(&error),
+        }
+        self.update_current();
     }
 
     pub fn get_cumulative_size(&mut self) {
@@ -236,22 +612,34 @@ impl System {
         }
     }
 
-    fn doing_search(&self) -> bool {
-        if let Some(InputMode::Search(_)) = self.context_ref().input_mode { true }
-        else { false }
+    fn doing_find(&self) -> bool {
+        matches!(self.context_ref().input_mode, Some(InputMode::Find(_)))
+    }
+
+    fn doing_grep(&self) -> bool {
+        matches!(self.context_ref().input_mode, Some(InputMode::Grep(_)))
+    }
+
+    // The index of the match under the cursor, once there are matches to browse
+    fn grep_index(&self) -> Option<usize> {
+        match self.context_ref().input_mode.as_ref() {
+            Some(InputMode::Grep(GrepTools {cursor_index: None, matches, index, ..}))
+                if !matches.is_empty() => Some(*index),
+            _ => None,
+        }
     }
 
-    fn collect_entries_that_match(orig: &Vec<DirEntry>, pattern: &str) -> Vec<DirEntry> {
-        orig.iter().filter(|e| System::contains_pattern(&e.name, &pattern)).map(|e| e.clone()).collect()
+    fn doing_search(&self) -> bool {
+        matches!(self.context_ref().input_mode, Some(InputMode::Search(_)))
     }
 
+
     pub fn start_changing_current_name(&mut self) {
         if self.inside_empty_dir() { return; }
         let old_name = self.unsafe_current_entry_ref().name.clone();
-        let old_name_len = old_name.len();
         self.context_mut().input_mode = Some(InputMode::ChangeName(ChangeNameTools {
+            cursor_index: chars_amount(&old_name),
             new_name: old_name,
-            cursor_index: old_name_len,
         }));
         System::reveal_cursor();
     }
@@ -264,21 +652,261 @@ impl System {
         System::reveal_cursor();
     }
 
+    pub fn start_editing_filter(&mut self) {
+        let text = self.current_tab_ref().filter.as_ref()
+            .map(|filter| filter.text.clone()).unwrap_or_default();
+        self.reset_input_mode_and_restore();
+        self.context_mut().input_mode = Some(InputMode::Filter(FilterTools {
+            cursor_index: chars_amount(&text),
+            text,
+        }));
+        System::reveal_cursor();
+    }
+
+    pub fn clear_filter(&mut self) {
+        if self.current_tab_ref().filter.is_none() { return; }
+        self.reset_input_mode_and_restore();
+        self.set_filter(None);
+        self.notify("Filter cleared");
+    }
+
+    // Keeps the cursor on the same entry, if it passes
+    fn set_filter(&mut self, filter: Option<NameFilter>) {
+        let name = if self.inside_empty_dir() { None }
+                   else { Some(self.unsafe_current_entry_ref().name.clone()) };
+        self.current_tab_mut().filter = filter;
+        self.update();
+        if let Some(name) = name { self.move_cursor_to(&name); }
+    }
+
+    pub fn start_selecting_matching(&mut self, select: bool) {
+        self.reset_input_mode_and_restore(); // the query goes through the whole directory
+        self.context_mut().input_mode = Some(InputMode::Select(SelectTools {
+            text: String::new(),
+            cursor_index: 0,
+            select,
+        }));
+        System::reveal_cursor();
+    }
+
+    pub fn start_pattern_rename(&mut self) {
+        self.reset_input_mode_and_restore(); // the targets are what is on screen
+        self.context_mut().input_mode = Some(InputMode::PatternRename(PatternRenameTools {
+            pattern: String::new(),
+            cursor_index: 0,
+        }));
+        System::reveal_cursor();
+    }
+
+    // Links point to the selection, or to the current entry if nothing is selected
+    pub fn start_creating(&mut self, kind: CreationKind) {
+        let kind = match kind {
+            CreationKind::Symlink { relative, .. } =>
+                CreationKind::Symlink { targets: self.selected_or_current(), relative },
+            CreationKind::HardLink(_) => CreationKind::HardLink(self.selected_or_current()),
+            other => other,
+        };
+        match kind {
+            CreationKind::Symlink { ref targets, .. } | CreationKind::HardLink(ref targets)
+                if targets.is_empty() => return self.notify("Nothing to link to"),
+            _ => {},
+        }
+        self.reset_input_mode_and_restore();
+        let text = initial_text_of(&kind);
+        self.context_mut().input_mode = Some(InputMode::Create(CreateTools {
+            cursor_index: chars_amount(&text),
+            text,
+            kind,
+        }));
+        System::reveal_cursor();
+    }
+
+    pub fn start_editing_permissions(&mut self) {
+        let paths = self.selected_or_current();
+        if paths.is_empty() { return; }
+        match PermissionsEditor::new(paths) {
+            Ok(editor) => {
+                self.reset_input_mode_and_restore();
+                self.context_mut().input_mode = Some(InputMode::Permissions(editor));
+            },
+            Err(error) => self.notify(&error),
+        }
+    }
+
     // XXX: Expects that it is not possible to directly switch from one mode to another
     pub fn start_search(&mut self) {
         if let Some(InputMode::Search(search_tools)) = self.context_mut().input_mode.as_mut() {
             // Continue previously started search
-            search_tools.cursor_index = Some(search_tools.query.len());
+            search_tools.cursor_index = Some(chars_amount(&search_tools.query));
         } else { // create a new search instance
             self.context_mut().input_mode = Some(InputMode::Search(SearchTools {
                 query: "".to_string(),
                 cursor_index: Some(0),
                 current_siblings_backup: self.context_ref().current_siblings.clone(),
+                mode: self.search_mode,
             }));
         }
         System::reveal_cursor();
     }
 
+    // Refilters the siblings with the same query
+    pub fn cycle_search_mode(&mut self) {
+        let mode = if let Some(InputMode::Search(search_tools)) = self.context_mut().input_mode.as_mut() {
+            search_tools.mode = next_search_mode(search_tools.mode);
+            search_tools.mode
+        } else { return; };
+        self.search_mode = mode;
+        self.refilter_search();
+    }
+
+    fn refilter_search(&mut self) {
+        let matching = match self.context_ref().input_mode.as_ref() {
+            Some(InputMode::Search(SearchTools {query, current_siblings_backup, mode, ..})) =>
+                filter_entries(current_siblings_backup, *mode, query),
+            _ => return,
+        };
+        self.context_mut().current_siblings = matching;
+        self.update_current_without_siblings();
+    }
+
+    // Walks the tree below the current directory
+    pub fn start_find(&mut self) {
+        if let Some(InputMode::Find(find_tools)) = self.context_mut().input_mode.as_mut() {
+            // Edit the query of the previous find
+            find_tools.cursor_index = Some(chars_amount(&find_tools.query));
+        } else {
+            self.reset_input_mode_and_restore();
+            self.context_mut().input_mode = Some(InputMode::Find(FindTools {
+                query: "".to_string(),
+                cursor_index: Some(0),
+                current_siblings_backup: self.context_ref().current_siblings.clone(),
+                searching: false,
+            }));
+        }
+        System::reveal_cursor();
+    }
+
+    // Starts over with the current query, the found entries replace the siblings
+    fn begin_find(&mut self) {
+        let query = match self.context_mut().input_mode.as_mut() {
+            Some(InputMode::Find(find_tools)) => {
+                find_tools.cursor_index = None;
+                find_tools.searching = true;
+                find_tools.query.clone()
+            },
+            _ => return,
+        };
+        self.find = Some(Find::start(self.context_ref().parent_path.clone(), &query,
+                                     self.show_hidden, self.settings.find_ignore.clone()));
+        self.context_mut().current_siblings.clear();
+        self.context_mut().current_index = 0;
+        self.update_current_without_siblings();
+        self.update_drawing_delay();
+    }
+
+    // Searches the contents of the files in the tree below the current directory
+    pub fn start_grep(&mut self) {
+        if let Some(InputMode::Grep(grep_tools)) = self.context_mut().input_mode.as_mut() {
+            // Edit the query of the previous search
+            grep_tools.cursor_index = Some(chars_amount(&grep_tools.query));
+        } else {
+            self.reset_input_mode_and_restore();
+            self.context_mut().input_mode = Some(InputMode::Grep(GrepTools {
+                query: "".to_string(),
+                cursor_index: Some(0),
+                matches: Vec::new(),
+                index: 0,
+                shift: 0,
+                searching: false,
+            }));
+        }
+        System::reveal_cursor();
+    }
+
+    // On an invalid regex the query stays to be fixed
+    fn begin_grep(&mut self) {
+        let query = match self.context_ref().input_mode.as_ref() {
+            Some(InputMode::Grep(grep_tools)) => grep_tools.query.clone(),
+            _ => return,
+        };
+        let pattern = match parse_grep_query(&query) {
+            Ok(pattern) => pattern,
+            Err(error)  => return self.notify(&error),
+        };
+        if let Some(InputMode::Grep(grep_tools)) = self.context_mut().input_mode.as_mut() {
+            grep_tools.cursor_index = None;
+            grep_tools.matches.clear();
+            grep_tools.index = 0;
+            grep_tools.shift = 0;
+            grep_tools.searching = true;
+        }
+        self.grep = Some(Grep::start(self.context_ref().parent_path.clone(), pattern,
+                                     self.show_hidden, self.settings.find_ignore.clone()));
+        self.update_drawing_delay();
+    }
+
+    // What has been found stays in the tab that is left, the walk does not
+    fn stop_searching(&mut self) {
+        if self.find.is_none() && self.grep.is_none() { return; }
+        self.find = None;
+        self.grep = None;
+        match self.context_mut().input_mode.as_mut() {
+            Some(InputMode::Find(find_tools)) => find_tools.searching = false,
+            Some(InputMode::Grep(grep_tools)) => grep_tools.searching = false,
+            _ => {},
+        }
+    }
+
+    fn move_grep_cursor_to(&mut self, new_index: usize) {
+        let gap = self.renderer.display_settings.scrolling_gap;
+        let height = self.renderer.display_settings.column_effective_height;
+        if let Some(InputMode::Grep(GrepTools {matches, index, shift, ..})) =
+                self.context_mut().input_mode.as_mut() {
+            if matches.is_empty() { return; }
+            *index = new_index.min(matches.len() - 1);
+            *shift = siblings_shift_for(gap, height, *index, matches.len(), Some(*shift));
+        }
+        self.show_grep_match();
+    }
+
+    // The file of the match becomes the current path, previewed around the line
+    fn show_grep_match(&mut self) {
+        let found = match self.context_ref().input_mode.as_ref() {
+            Some(InputMode::Grep(GrepTools {matches, index, ..})) if !matches.is_empty()
+                => matches[*index].clone(),
+            _ => return,
+        };
+        let (begin, end) = self.renderer.display_settings.columns_coord[2];
+        let height = self.renderer.display_settings.column_effective_height;
+        let path = self.context_ref().parent_path.join(&found.path);
+        self.context_mut().right_column = RightColumn::with_preview(
+            preview_around(&path, found.line, height, (end - begin) as usize));
+        self.context_mut().current_details = details_of(&path).ok();
+        self.context_mut().additional_entry_info = None;
+        self.context_mut().cumulative_size_text = None;
+        self.context_mut().current_path = Some(path);
+    }
+
+    fn open_grep_match(&mut self) {
+        let line = match self.context_ref().input_mode.as_ref() {
+            Some(InputMode::Grep(GrepTools {matches, index, ..})) => matches[*index].line,
+            _ => return,
+        };
+        let path = self.context_ref().current_path.clone().unwrap();
+        if let Err(error) = edit_file_at_line(&path, line, &self.spawn_patterns) { self.notify(&error); }
+        self.renderer.invalidate(); // Otherwise the screen is not restored correctly
+        self.show_grep_match(); // the file may have changed
+    }
+
+    // Leaves the found entries for the directory of the one under the cursor
+    fn jump_to_found(&mut self) {
+        let path = self.context_ref().current_path.clone().unwrap();
+        self.cancel_input();
+        let dir = path.parent().unwrap().to_path_buf();
+        if dir != self.context_ref().parent_path { self.goto_path(dir); }
+        self.move_cursor_to(&file_name(&path));
+    }
+
     fn reset_input_mode(&mut self) {
         self.context_mut().input_mode = None;
         System::hide_cursor();
@@ -288,13 +916,19 @@ impl System {
         if let Some(InputMode::Search(search_tools)) = self.context_mut().input_mode.as_mut() {
             self.context_mut().current_siblings =
                 search_tools.current_siblings_backup.drain(..).collect();
+        } else if let Some(InputMode::Find(find_tools)) = self.context_mut().input_mode.as_mut() {
+            self.context_mut().current_siblings =
+                find_tools.current_siblings_backup.drain(..).collect();
+            self.find = None; // stops the walk
+        } else if let Some(InputMode::Grep(_)) = self.context_ref().input_mode.as_ref() {
+            self.grep = None;
         } // other modes don't need any restoration
         self.reset_input_mode();
     }
 
     pub fn cancel_input(&mut self) {
         let was_doing_search = match self.context_ref().input_mode {
-            Some(InputMode::Search(_)) => true,
+            Some(InputMode::Search(_)) | Some(InputMode::Find(_)) | Some(InputMode::Grep(_)) => true,
             _ => false,
         };
         self.reset_input_mode_and_restore();
@@ -306,11 +940,27 @@ impl System {
     pub fn confirm_input(&mut self) {
         if let Some(InputMode::Search(search_tools)) = self.context_mut().input_mode.as_mut() {
             search_tools.cursor_index = None;
+        } else if let Some(InputMode::Find(_)) = self.context_ref().input_mode.as_ref() {
+            self.begin_find();
+        } else if let Some(InputMode::Grep(_)) = self.context_ref().input_mode.as_ref() {
+            self.begin_grep();
         } else if let Some(InputMode::ChangeName(ChangeNameTools {new_name, ..})) =
                 self.context_ref().input_mode.as_ref() {
-            System::rename(self.context_ref().current_path.as_ref().unwrap(), &new_name);
-            self.update_current();
-            self.context_mut().input_mode = None;
+            let path = self.context_ref().current_path.clone().unwrap();
+            let new_name = new_name.clone();
+            let new_path = path.parent().unwrap().join(&new_name);
+            if self.settings.confirm_rename && new_path != path {
+                let question = if exists(&new_path) {
+                    format!("Rename {} to {}, replacing the existing one?", file_name(&path), new_name)
+                } else {
+                    format!("Rename {} to {}?", file_name(&path), new_name)
+                };
+                self.ask(question, ConfirmedAction::Rename(path, new_name));
+            } else {
+                self.rename(&path, &new_name);
+                self.update_current();
+                self.context_mut().input_mode = None;
+            }
         } else if let Some(InputMode::Command(CommandTools {text, ..})) =
                 self.context_ref().input_mode.as_ref() {
             execute_command_from(&self.context_ref().parent_path, text);
@@ -318,6 +968,67 @@ impl System {
             // hasn't finished yet => no use updating.
             // self.update_current();
             self.context_mut().input_mode = None;
+        } else if let Some(InputMode::PatternRename(PatternRenameTools {pattern, ..})) =
+                self.context_ref().input_mode.as_ref() {
+            // On a mistake the pattern stays to be fixed
+            match self.planned_pattern_renames(pattern) {
+                Ok(ref renames) if renames.is_empty() => {
+                    self.notify("Nothing to rename");
+                    self.context_mut().input_mode = None;
+                },
+                Ok(renames) => match check_renames(&renames) {
+                    Ok(()) => {
+                        self.context_mut().input_mode = None;
+                        self.apply_renames(renames);
+                    },
+                    Err(error) => return self.notify(&error),
+                },
+                Err(error) => return self.notify(&error),
+            }
+        } else if let Some(InputMode::Select(SelectTools {text, select, ..})) =
+                self.context_ref().input_mode.as_ref() {
+            let select = *select;
+            match parse_selection_query(text) {
+                Ok(criteria) => {
+                    self.context_mut().input_mode = None;
+                    self.select_matching(&criteria, select);
+                },
+                Err(error) => return self.notify(&error), // stays to be fixed
+            }
+        } else if let Some(InputMode::Filter(FilterTools {text, ..})) =
+                self.context_ref().input_mode.as_ref() {
+            // An empty one clears the filter, a wrong one stays to be fixed
+            let filter = if text.is_empty() { None } else {
+                match parse_name_filter(text) {
+                    Ok(filter) => Some(filter),
+                    Err(error) => return self.notify(&error),
+                }
+            };
+            self.context_mut().input_mode = None;
+            self.set_filter(filter);
+        } else if let Some(InputMode::Create(CreateTools {kind, text, ..})) =
+                self.context_ref().input_mode.as_ref() {
+            let (kind, text) = (kind.clone(), text.clone());
+            self.context_mut().input_mode = None;
+            self.create(&kind, &text);
+        } else if let Some(InputMode::Permissions(editor)) = self.context_mut().input_mode.as_mut() {
+            if editor.field != EditedField::Mode { return editor.finish_name(); }
+            match editor.apply() {
+                Ok((changed, errors)) => {
+                    self.context_mut().input_mode = None;
+                    self.selected.clear();
+                    if errors.is_empty() {
+                        self.notify(&format!("Changed {} {}", changed,
+                                             if changed == 1 { "entry" } else { "entries" }));
+                    } else {
+                        self.report_errors(&errors);
+                    }
+                    self.update_current();
+                },
+                Err(error) => self.notify(&error), // e.g. an unknown user, the editor stays
+            }
+        } else if let Some(InputMode::Confirm(_)) = self.context_ref().input_mode.as_ref() {
+            self.answer_confirmation(false); // the default answer is no
         }
         System::hide_cursor();
     }
@@ -328,11 +1039,25 @@ impl System {
             if *cursor_index >= 1 {
                 *cursor_index -= 1;
             }
-        } else if let Some(InputMode::Command(CommandTools {cursor_index, ..})) =
+        } else if let Some(InputMode::Command(CommandTools {cursor_index, ..})) |
+                      Some(InputMode::Filter(FilterTools {cursor_index, ..})) |
+                      Some(InputMode::Select(SelectTools {cursor_index, ..})) =
                 self.context_mut().input_mode.as_mut() {
             if *cursor_index >= 1 {
                 *cursor_index -= 1;
             }
+        } else if let Some(InputMode::PatternRename(PatternRenameTools {cursor_index, ..})) =
+                self.context_mut().input_mode.as_mut() {
+            if *cursor_index >= 1 {
+                *cursor_index -= 1;
+            }
+        } else if let Some(InputMode::Create(CreateTools {cursor_index, ..})) =
+                self.context_mut().input_mode.as_mut() {
+            if *cursor_index >= 1 {
+                *cursor_index -= 1;
+            }
+        } else if let Some(InputMode::Permissions(editor)) = self.context_mut().input_mode.as_mut() {
+            if editor.field == EditedField::Mode { editor.handle_key('h'); }
         }
         // XXX: This is a TODO
         // Not currently implemented because be can only add/remove chars at the
@@ -350,14 +1075,32 @@ impl System {
     pub fn move_input_cursor_right(&mut self) {
         if let Some(InputMode::ChangeName(ChangeNameTools {cursor_index, new_name})) =
                 self.context_mut().input_mode.as_mut() {
-            if *cursor_index + 1 <= new_name.len() { // allow one after end of text
+            if *cursor_index < chars_amount(new_name) { // allow one after end of text
                 *cursor_index += 1;
             }
         } else if let Some(InputMode::Command(CommandTools {cursor_index, text})) =
                 self.context_mut().input_mode.as_mut() {
-            if *cursor_index + 1 <= text.len() { // allow one after end of text
+            if *cursor_index < text.len() { // allow one after end of text
                 *cursor_index += 1;
             }
+        } else if let Some(InputMode::Filter(FilterTools {cursor_index, text})) |
+                      Some(InputMode::Select(SelectTools {cursor_index, text, ..})) =
+                self.context_mut().input_mode.as_mut() {
+            if *cursor_index < chars_amount(text) { // allow one after end of text
+                *cursor_index += 1;
+            }
+        } else if let Some(InputMode::PatternRename(PatternRenameTools {cursor_index, pattern})) =
+                self.context_mut().input_mode.as_mut() {
+            if *cursor_index < chars_amount(pattern) { // allow one after end of text
+                *cursor_index += 1;
+            }
+        } else if let Some(InputMode::Create(CreateTools {cursor_index, text, ..})) =
+                self.context_mut().input_mode.as_mut() {
+            if *cursor_index < chars_amount(text) { // allow one after end of text
+                *cursor_index += 1;
+            }
+        } else if let Some(InputMode::Permissions(editor)) = self.context_mut().input_mode.as_mut() {
+            if editor.field == EditedField::Mode { editor.handle_key('l'); }
         // XXX: This is a TODO
         // Not currently implemented because be can only add/remove chars at the
         // end of the search query as an optimization
@@ -377,8 +1120,16 @@ impl System {
         if !self.have_context() { return false; }
         match self.context_ref().input_mode.as_ref() {
             Some(InputMode::Search(search_tools)) => search_tools.cursor_index.is_some(),
+            Some(InputMode::Find(find_tools)) => find_tools.cursor_index.is_some(),
+            Some(InputMode::Grep(grep_tools)) => grep_tools.cursor_index.is_some(),
             Some(InputMode::ChangeName(_)) => true,
             Some(InputMode::Command(_)) => true,
+            Some(InputMode::Filter(_)) => true,
+            Some(InputMode::Select(_)) => true,
+            Some(InputMode::Confirm(_)) => true,
+            Some(InputMode::PatternRename(_)) => true,
+            Some(InputMode::Create(_)) => true,
+            Some(InputMode::Permissions(_)) => true,
             _ => false,
         }
     }
@@ -386,21 +1137,29 @@ impl System {
     // !!! The fact that new chars are inserted at the end is used as an optimization while
     // performing an insertion step
     pub fn insert_input(&mut self, c: char) {
+        if let Some(InputMode::Confirm(_)) = self.context_ref().input_mode.as_ref() {
+            match c {
+                'y' | 'Y' => self.answer_confirmation(true),
+                'n' | 'N' => self.answer_confirmation(false),
+                _ => {},
+            }
+            return;
+        }
         if System::valid_input(c) {
             match self.context_mut().input_mode.as_mut() {
                 Some(InputMode::Search(search_tools)) => {
                     search_tools.query.push(c);
                     search_tools.cursor_index.as_mut().map(|index| *index += 1);
-                    // Optimization: search only among the last known matches because the new ones
-                    // must be a subset of them due to appending chars to the end of query
-                    let pattern = search_tools.query.clone();
-                    self.context_mut().current_siblings.retain(|entry|
-                        System::contains_pattern(&entry.name, &pattern));
-                    self.update_current_without_siblings();
+                    // Not only among the last matches: a longer regex or glob may match more
+                    self.refilter_search();
+                },
+                Some(InputMode::Find(FindTools {query, cursor_index, ..})) |
+                Some(InputMode::Grep(GrepTools {query, cursor_index, ..})) => {
+                    query.push(c);
+                    if let Some(index) = cursor_index { *index += 1; }
                 },
                 Some(InputMode::ChangeName(ChangeNameTools {cursor_index, new_name})) => {
-                    // Trusts that the cursor index is valid
-                    new_name.insert(*cursor_index, c);
+                    new_name.insert(byte_index_of_char(new_name, *cursor_index), c);
                     *cursor_index += 1;
                 },
                 Some(InputMode::Command(CommandTools {cursor_index, text})) => {
@@ -408,6 +1167,20 @@ impl System {
                     text.insert(*cursor_index, c);
                     *cursor_index += 1;
                 },
+                Some(InputMode::Filter(FilterTools {cursor_index, text})) |
+                Some(InputMode::Select(SelectTools {cursor_index, text, ..})) => {
+                    text.insert(byte_index_of_char(text, *cursor_index), c);
+                    *cursor_index += 1;
+                },
+                Some(InputMode::PatternRename(PatternRenameTools {cursor_index, pattern})) => {
+                    pattern.insert(byte_index_of_char(pattern, *cursor_index), c);
+                    *cursor_index += 1;
+                },
+                Some(InputMode::Create(CreateTools {cursor_index, text, ..})) => {
+                    text.insert(byte_index_of_char(text, *cursor_index), c);
+                    *cursor_index += 1;
+                },
+                Some(InputMode::Permissions(editor)) => editor.handle_key(c),
                 _ => {},
             }
         }
@@ -415,55 +1188,77 @@ impl System {
 
     pub fn remove_input_before_cursor(&mut self) {
         match self.context_mut().input_mode.as_mut() {
-            Some(InputMode::Search(SearchTools {query, cursor_index, current_siblings_backup})) => {
+            Some(InputMode::Search(SearchTools {query, cursor_index, ..})) => {
                 if query.len() > 0 {
                     query.pop();
                     cursor_index.as_mut().map(|index| *index -= 1);
-
-                    let pattern = query.clone();
-                    self.context_mut().current_siblings = System::collect_entries_that_match(
-                        &current_siblings_backup, &pattern);
-                    self.update_current_without_siblings();
+                    self.refilter_search();
                 }
             },
-            Some(InputMode::ChangeName(ChangeNameTools {cursor_index, new_name})) => {
-                // Trusts that the cursor index is valid
-                if *cursor_index > 0 {
-                    *cursor_index -= 1;
-                    new_name.remove(*cursor_index);
-                }
+            Some(InputMode::Find(FindTools {query, cursor_index, ..})) |
+            Some(InputMode::Grep(GrepTools {query, cursor_index, ..})) => {
+                query.pop();
+                if let Some(index) = cursor_index { *index = chars_amount(query); }
+            },
+            Some(InputMode::ChangeName(ChangeNameTools {cursor_index, new_name}))
+                    if *cursor_index > 0 => {
+                *cursor_index -= 1;
+                new_name.remove(byte_index_of_char(new_name, *cursor_index));
             },
-            Some(InputMode::Command(CommandTools {cursor_index, text})) => {
+            Some(InputMode::Command(CommandTools {cursor_index, text}))
+                    if *cursor_index > 0 => {
                 // Trusts that the cursor index is valid
-                if *cursor_index > 0 {
-                    *cursor_index -= 1;
-                    text.remove(*cursor_index);
-                }
+                *cursor_index -= 1;
+                text.remove(*cursor_index);
+            },
+            Some(InputMode::Filter(FilterTools {cursor_index, text})) |
+            Some(InputMode::Select(SelectTools {cursor_index, text, ..}))
+                    if *cursor_index > 0 => {
+                *cursor_index -= 1;
+                text.remove(byte_index_of_char(text, *cursor_index));
+            },
+            Some(InputMode::PatternRename(PatternRenameTools {cursor_index, pattern}))
+                    if *cursor_index > 0 => {
+                *cursor_index -= 1;
+                pattern.remove(byte_index_of_char(pattern, *cursor_index));
+            },
+            Some(InputMode::Create(CreateTools {cursor_index, text, ..}))
+                    if *cursor_index > 0 => {
+                *cursor_index -= 1;
+                text.remove(byte_index_of_char(text, *cursor_index));
             },
+            Some(InputMode::Permissions(editor)) => editor.remove_last(),
             _ => {},
         }
     }
 
     pub fn remove_input_under_cursor(&mut self) {
         match self.context_mut().input_mode.as_mut() {
-            Some(InputMode::ChangeName(ChangeNameTools {cursor_index, new_name})) => {
-                new_name.remove(*cursor_index);
+            Some(InputMode::ChangeName(ChangeNameTools {cursor_index, new_name}))
+                    if *cursor_index < chars_amount(new_name) => {
+                new_name.remove(byte_index_of_char(new_name, *cursor_index));
             },
-            Some(InputMode::Command(CommandTools {cursor_index, text})) => {
+            Some(InputMode::Command(CommandTools {cursor_index, text}))
+                    if *cursor_index < text.len() => {
                 text.remove(*cursor_index);
             },
+            Some(InputMode::Filter(FilterTools {cursor_index, text})) |
+            Some(InputMode::Select(SelectTools {cursor_index, text, ..}))
+                    if *cursor_index < chars_amount(text) => {
+                text.remove(byte_index_of_char(text, *cursor_index));
+            },
+            Some(InputMode::PatternRename(PatternRenameTools {cursor_index, pattern}))
+                    if *cursor_index < chars_amount(pattern) => {
+                pattern.remove(byte_index_of_char(pattern, *cursor_index));
+            },
+            Some(InputMode::Create(CreateTools {cursor_index, text, ..}))
+                    if *cursor_index < chars_amount(text) => {
+                text.remove(byte_index_of_char(text, *cursor_index));
+            },
             _ => {},
         }
     }
 
-    fn contains_pattern(string: &str, pattern: &str) -> bool {
-        if pattern.is_empty() { return true; }
-        let pattern_lowercase = pattern.to_lowercase();
-        let case_sensitive = pattern_lowercase != pattern;
-        if case_sensitive { string               .contains( pattern          ) }
-        else              { string.to_lowercase().contains(&pattern_lowercase) }
-    }
-
     fn valid_input(c: char) -> bool {
         c.is_alphanumeric() || c == '_' || c == '!' || c == '@' || c == '#' || c == '$' ||
             c == '%' || c == '^' || c == '&' || c == '*' || c == '(' || c == ')' || c == '-' ||
@@ -477,8 +1272,23 @@ impl System {
         self.current_tab_mut().name = tab_name_from_path(parent_path);
     }
 
+    pub fn on_last_tab(&self) -> bool {
+        self.tabs.len() == 1
+    }
+
+    pub fn exit_state(&self) -> ExitState {
+        let chosen = if self.selected.is_empty() {
+            self.context_ref().current_path.iter().cloned().collect()
+        } else { self.selected.clone() };
+        ExitState {
+            dir: self.context_ref().parent_path.clone(),
+            chosen,
+        }
+    }
+
     // Returns whether it was the last Tab (perhaps whether we should terminate)
     pub fn close_tab(&mut self) -> bool {
+        self.stop_searching();
         self.tabs.remove(self.current_tab_index);
         if self.tabs.is_empty() {
             self.current_tab_index = 0;
@@ -491,6 +1301,7 @@ impl System {
     pub fn new_tab(&mut self) {
         const MAX_TABS: usize = 8;
         if self.tabs.len() + 1 > MAX_TABS { return; }
+        self.stop_searching();
         // The trick here is that we create a copy of the current tab and refer
         // to the copy as to the old tab, and start reigning in the new one
         self.tabs.push(self.current_tab_ref().clone());
@@ -498,6 +1309,7 @@ impl System {
     }
 
     pub fn next_tab(&mut self) {
+        self.stop_searching();
         if self.current_tab_index == self.tabs.len() - 1 {
             self.current_tab_index = 0;
         } else {
@@ -506,7 +1318,23 @@ impl System {
         if self.tabs.len() > 1 { self.update_current(); }
     }
 
+    // Counting from 1, like `5gt` in vim; past the last one goes to the last one
+    pub fn go_to_tab(&mut self, number: usize) {
+        let index = number.max(1).min(self.tabs.len()) - 1;
+        if index == self.current_tab_index { return; }
+        self.stop_searching();
+        self.current_tab_index = index;
+        self.update_current();
+    }
+
+    // Wraps around, like `3gT` in vim
+    pub fn previous_tab_by(&mut self, amount: usize) {
+        let len = self.tabs.len();
+        self.go_to_tab((self.current_tab_index + len - amount % len) % len + 1);
+    }
+
     pub fn previous_tab(&mut self) {
+        self.stop_searching();
         if self.current_tab_index == 0 {
             self.current_tab_index = self.tabs.len() - 1;
         } else {
@@ -516,17 +1344,58 @@ impl System {
     }
 //-----------------------------------------------------------------------------
     pub fn sort_with(&mut self, new_sorting_type: SortingType) {
-        self.sorting_type = new_sorting_type;
+        let mut sorting = self.current_sorting();
+        sorting.sorting_type = new_sorting_type;
+        self.set_sorting(sorting);
+    }
+
+    pub fn toggle_sort_reverse(&mut self) {
+        let mut sorting = self.current_sorting();
+        sorting.reverse = !sorting.reverse;
+        self.set_sorting(sorting);
+    }
+
+    pub fn toggle_directories_first(&mut self) {
+        let mut sorting = self.current_sorting();
+        sorting.directories_first = !sorting.directories_first;
+        self.set_sorting(sorting);
+    }
+
+    // Applies to the current directory only, when sorting per directory, or else
+    // to the whole tab. The cursor stays on the same entry.
+    fn set_sorting(&mut self, sorting: Sorting) {
+        let parent_path = self.context_ref().parent_path.clone();
+        if self.settings.sort_per_directory {
+            self.directory_sortings.insert(parent_path.clone(), sorting);
+        } else {
+            self.current_tab_mut().sorting = sorting;
+        }
+        self.context_mut().parent_siblings = self.collect_sorted_siblings_of_parent();
+        if let Some(index) = index_of_entry_inside(&parent_path, &self.context_ref().parent_siblings) {
+            self.context_mut().parent_index = index;
+        }
+        self.context_mut().parent_siblings_shift = self.recalculate_parent_siblings_shift();
+        let current_path = self.context_ref().current_path.clone();
         self.update_current();
+        if let Some(index) = current_path.and_then(
+                |path| index_of_entry_inside(&path, &self.context_ref().current_siblings)) {
+            self.context_mut().current_index = index;
+            self.update_current_without_siblings();
+        }
+        self.notify(&format!("Sorted by {}", sorting_description(&sorting)));
     }
 
 //-----------------------------------------------------------------------------
-    fn get_current_permissions(&mut self) -> Option<String> {
-        string_permissions_for_entry(&self.current_entry_ref())
+    fn get_current_details(&self) -> Option<Details> {
+        self.context_ref().current_path.as_ref().and_then(|path| details_of(path).ok())
     }
 
     fn get_additional_entry_info_for_current(&self) -> Option<String> {
         let context = self.context_ref();
+        if let Some(info) = context.current_path.as_ref().and_then(|path| trash_info_of(path)) {
+            return Some(format!("Trashed from {} on {}",
+                                info.original_path.display(), info.deletion_date));
+        }
         get_additional_entry_info(self.current_entry_ref(), &context.current_path)
     }
 
@@ -586,13 +1455,14 @@ impl System {
     // Update central column and right column
     pub fn update_current(&mut self) {
         let new_siblings = self.collect_sorted_children_of_parent();
-        if let Some(InputMode::Search(search_tools)) = self.context_ref().input_mode.as_ref() {
-            let pattern = search_tools.query.clone();
-            self.context_mut().current_siblings = System::collect_entries_that_match(
-                &new_siblings, &pattern);
+        if let Some(InputMode::Search(SearchTools {query, mode, ..})) = self.context_ref().input_mode.as_ref() {
+            let matching = filter_entries(&new_siblings, *mode, query);
+            self.context_mut().current_siblings = matching;
         }
         if let Some(InputMode::Search(search_tools)) = self.context_mut().input_mode.as_mut() {
             search_tools.current_siblings_backup = new_siblings;
+        } else if let Some(InputMode::Find(find_tools)) = self.context_mut().input_mode.as_mut() {
+            find_tools.current_siblings_backup = new_siblings;
         } else {
             self.context_mut().current_siblings = new_siblings;
         }
@@ -609,7 +1479,7 @@ impl System {
             self.context_ref().current_index, &self.context_ref().parent_path,
             &self.context_ref().current_siblings);
         self.context_mut().right_column = self.collect_right_column_of_current();
-        self.context_mut().current_permissions = self.get_current_permissions();
+        self.context_mut().current_details = self.get_current_details();
         self.context_mut().current_siblings_shift = self.recalculate_current_siblings_shift();
         self.context_mut().additional_entry_info = self.get_additional_entry_info_for_current();
         if let Some(path) = self.context_ref().current_path.as_ref() {
@@ -617,52 +1487,107 @@ impl System {
                 self.unsafe_current_entry_mut().size = new_size;
             }
         }
+        if self.grep_index().is_some() { self.show_grep_match(); }
     }
 
     fn update_transfer_progress(&mut self) {
         let mut finished_some = false;
+        let mut errors = Vec::new();
+        let mut batches = Vec::new();
         for transfer in self.transfers.iter_mut() {
-            let dst_sizes: Vec<Size> = transfer.dst_paths.iter()
-                .zip(transfer.dst_sizes.iter())
-                .map(|(path, &size)|
-                     if let Some(size) = size { size } else { cumulative_size(path) })
-                .collect();
-            for (index, &size) in dst_sizes.iter().enumerate() {
-                let finished_this_one = size == transfer.src_sizes[index];
-                if finished_this_one { // then cache for later
-                    transfer.dst_sizes   [index] = Some(size);
-                }
-            }
-            let src_cumulative_size: Size = transfer.src_sizes.iter().sum();
-            let dst_cumulative_size: Size =          dst_sizes.iter().sum();
-
-            if src_cumulative_size == dst_cumulative_size { // finished
+            transfer.poll();
+            if transfer.finished {
                 // Can remove this transfer now. Do it after this loop with retain()
                 let text = match transfer.transfer_type {
                     TransferType::Cut  => "Done moving!",
                     TransferType::Yank => "Done copying!",
                 };
-                self.notification = Some(Notification::new(text, 3000));
+                self.notification = Some(Notification::new(text,
+                    self.settings.copy_done_notification_delay_ms));
+                errors.append(&mut transfer.errors);
                 finished_some = true;
-            } else { // partially finished
-                let percentage = (100 * dst_cumulative_size / src_cumulative_size) as u32;
-                let text = match transfer.transfer_type {
-                    TransferType::Cut  => format!("Moving...({}% done)", percentage),
-                    TransferType::Yank => format!("Copying...({}% done)", percentage),
-                };
-                self.notification = Some(Notification::new(&text, 3000));
-                System::set_drawing_delay(DrawingDelay::Transfering);
+                let transfer_type = transfer.transfer_type;
+                let batch = transfer.placed.drain(..).map(|(from, to)| match transfer_type {
+                    TransferType::Cut  => Operation::Move { from, to },
+                    TransferType::Yank => Operation::Copy { from, to },
+                }).collect();
+                batches.push(batch);
             }
         }
 
-        // TODO: mb remove
-        if finished_some || !self.transfers.is_empty() { self.update_current(); }
+        // The new entries show up once the transfer is done, not on every redraw
+        if finished_some { self.update_current(); }
+
+        self.transfers.retain(|t| !t.finished);
+        for batch in batches.into_iter() { self.record(batch); }
+        if !errors.is_empty() { self.report_errors(&errors); }
+
+        self.update_drawing_delay();
+    }
+
+    fn update_find_results(&mut self) {
+        let (found, finished, unreadable) = match self.find.as_mut() {
+            Some(find) => (find.poll(), find.finished, find.unreadable),
+            None       => return,
+        };
+        if !self.doing_find() { // left some other way
+            self.find = None;
+            return self.update_drawing_delay();
+        }
+        let parent_path = self.context_ref().parent_path.clone();
+        let was_empty = self.context_ref().current_siblings.is_empty();
+        let any_found = !found.is_empty();
+        let found = into_direntries(found, &self.settings.paint_settings, &self.selected, Some(&parent_path));
+        self.context_mut().current_siblings.extend(found);
+        if finished {
+            self.stop_searching();
+            self.update_drawing_delay();
+            if unreadable > 0 {
+                self.notify(&format!("Could not read {} {}", unreadable,
+                                     if unreadable == 1 { "directory" } else { "directories" }));
+            }
+        }
+        if was_empty && any_found { self.update_current_without_siblings(); }
+    }
 
-        // Leave only the ones with uncompleted (None) sizes left
-        self.transfers.retain(|t| !t.dst_sizes.iter().all(|&size| size.is_some()));
+    fn update_grep_results(&mut self) {
+        let (found, finished, unreadable, truncated) = match self.grep.as_mut() {
+            Some(grep) => (grep.poll(), grep.finished, grep.unreadable, grep.truncated),
+            None       => return,
+        };
+        if !self.doing_grep() { // left some other way
+            self.grep = None;
+            return self.update_drawing_delay();
+        }
+        let mut was_empty = false;
+        if let Some(InputMode::Grep(GrepTools {matches, ..})) = self.context_mut().input_mode.as_mut() {
+            was_empty = matches.is_empty();
+            matches.extend(found);
+        }
+        if finished {
+            self.stop_searching();
+            self.update_drawing_delay();
+            if truncated { self.notify("Stopped at 10000 matches"); }
+            else if unreadable > 0 { self.notify(&format!("Could not read {} entries", unreadable)); }
+        }
+        if was_empty { self.show_grep_match(); }
+    }
 
-        // Slow down the pace
-        if self.transfers.is_empty() { System::set_drawing_delay(DrawingDelay::Regular); }
+    // Keep redrawing often while the progress is changing, otherwise slow down the pace
+    fn update_drawing_delay(&self) {
+        if self.find.is_some() || self.grep.is_some() {
+            System::set_drawing_delay(DrawingDelay::Finding);
+        }
+        else if !self.transfers.is_empty() { System::set_drawing_delay(DrawingDelay::Transfering); }
+        else                               { System::set_drawing_delay(DrawingDelay::Regular); }
+    }
+
+    pub fn toggle_transfers_popup(&mut self) {
+        self.show_transfers = !self.show_transfers;
+    }
+
+    pub fn toggle_details_popup(&mut self) {
+        self.show_details = !self.show_details;
     }
 
     fn update_notification(&mut self) {
@@ -675,16 +1600,19 @@ impl System {
 //-----------------------------------------------------------------------------
     fn collect_sorted_siblings_of_parent(&self) -> Vec<DirEntry> {
         let grandparent = maybe_parent(&self.context_ref().parent_path);
+        let sorting = grandparent.as_ref().map(|path| self.sorting_for(path))
+            .unwrap_or(self.current_tab_ref().sorting);
         into_sorted_direntries(
             collect_siblings_of(&self.context_ref().parent_path, self.show_hidden),
-            &self.settings.paint_settings, &self.sorting_type,
+            &self.settings.paint_settings, &sorting, self.current_tab_ref().filter.as_ref(),
             &self.selected, grandparent.as_ref())
     }
 
     fn collect_sorted_children_of_parent(&self) -> Vec<DirEntry> {
         into_sorted_direntries(
             collect_maybe_dir(&self.context_ref().parent_path, None, self.show_hidden),
-            &self.settings.paint_settings, &self.sorting_type,
+            &self.settings.paint_settings, &self.current_sorting(),
+            self.current_tab_ref().filter.as_ref(),
             &self.selected, Some(&self.context_ref().parent_path)) // TODO: CHECK
     }
 
@@ -709,7 +1637,7 @@ impl System {
     fn update_current_entry_by_index(&mut self) {
         self.context_mut().cumulative_size_text = None;
         self.update_last_part_of_current_path_by_index();
-        self.context_mut().current_permissions = self.get_current_permissions();
+        self.context_mut().current_details = self.get_current_details();
         self.context_mut().right_column = self.collect_right_column_of_current();
         self.context_mut().current_siblings_shift = self.recalculate_current_siblings_shift();
         self.context_mut().additional_entry_info = self.get_additional_entry_info_for_current();
@@ -721,10 +1649,9 @@ impl System {
     }
 
     fn update_last_part_of_current_path_by_index(&mut self) {
-        let name = self.unsafe_current_entry_ref().name.clone();
-        self.context_mut().current_path.as_mut().map(|path| {
-            (*path).set_file_name(name); // try_pop + push(name)
-        });
+        // The found entries are named by their relative paths
+        let path = self.context_ref().parent_path.join(&self.unsafe_current_entry_ref().name);
+        self.context_mut().current_path = Some(path);
     }
 
     fn common_left_right(&mut self) {
@@ -733,28 +1660,38 @@ impl System {
         self.context_mut().current_siblings = self.collect_sorted_children_of_parent();
         self.context_mut().parent_siblings = self.collect_sorted_siblings_of_parent();
         self.context_mut().additional_entry_info = self.get_additional_entry_info_for_current();
-        self.context_mut().current_permissions = self.get_current_permissions();
+        self.context_mut().current_details = self.get_current_details();
         self.reset_input_mode();
         self.update_current_tab_name();
     }
 //-----------------------------------------------------------------------------
-    pub fn up(&mut self) {
+    pub fn down(&mut self) {
+        self.down_by(1);
+    }
+
+    // Stops at the first entry
+    pub fn up_by(&mut self, amount: usize) {
+        if let Some(index) = self.grep_index() { return self.move_grep_cursor_to(index.saturating_sub(amount)); }
         if self.inside_empty_dir() { return }
         if self.context_ref().current_index > 0 {
-            self.context_mut().current_index -= 1;
+            self.context_mut().current_index = self.context_ref().current_index.saturating_sub(amount);
             self.update_current_entry_by_index();
         }
     }
 
-    pub fn down(&mut self) {
+    // Stops at the last entry
+    pub fn down_by(&mut self, amount: usize) {
+        if let Some(index) = self.grep_index() { return self.move_grep_cursor_to(index.saturating_add(amount)); }
         if self.inside_empty_dir() { return }
-        if self.context_ref().current_index < self.context_ref().current_siblings.len() - 1 {
-            self.context_mut().current_index += 1;
+        let last_index = self.context_ref().current_siblings.len() - 1;
+        if self.context_ref().current_index < last_index {
+            self.context_mut().current_index = self.context_ref().current_index.saturating_add(amount).min(last_index);
             self.update_current_entry_by_index();
         }
     }
 
     pub fn left(&mut self) {
+        if self.doing_find() || self.doing_grep() { return self.cancel_input(); } // back to the siblings
         if !is_root(&self.context_ref().parent_path) {
             if self.context_ref().current_path.is_none() {
                 self.context_mut().current_path = Some(self.context_ref().parent_path.clone());
@@ -774,7 +1711,9 @@ impl System {
     }
 
     pub fn right(&mut self) {
+        if self.grep_index().is_some() { return self.open_grep_match(); }
         if self.inside_empty_dir() { return; }
+        if self.doing_find() { return self.jump_to_found(); }
         // Have to resort to cloning so that Rust does not complain about immutable reference:
         let current_path = self.context_ref().current_path.as_ref().unwrap().clone();
         if current_path.is_dir() { // Traverses symlinks. The resolved path points to a dir
@@ -801,68 +1740,237 @@ impl System {
     }
 
     pub fn goto(&mut self, path: &str) {
-        self.context_mut().parent_path = PathBuf::from(path);
+        self.goto_path(expand_path(path));
+    }
+
+    // For the paths that come from the filesystem, where `~` and `$` are just chars
+    fn goto_path(&mut self, path: PathBuf) {
+        if !path.is_absolute() || !path.is_dir() {
+            self.notify(&format!("Not a directory: {}", path_to_str(&path)));
+            return;
+        }
+        self.context_mut().parent_path = path;
         self.update();
     }
 
+    pub fn apply_bookmark_action(&mut self, action: BookmarkAction, key: char) {
+        match action {
+            BookmarkAction::GoTo => {
+                match self.bookmarks.get(key) {
+                    Some(path) => self.goto_path(path),
+                    None       => self.notify(&format!("No bookmark under '{}'", key)),
+                }
+            },
+            BookmarkAction::Add => {
+                let path = self.context_ref().parent_path.clone();
+                match self.bookmarks.add(key, &path) {
+                    Ok(())     => self.notify(&format!("Bookmarked {} under '{}'", path.display(), key)),
+                    Err(error) => self.notify(&error),
+                }
+            },
+            BookmarkAction::Remove => {
+                match self.bookmarks.remove(key) {
+                    Ok(true)   => self.notify(&format!("Removed bookmark '{}'", key)),
+                    Ok(false)  => self.notify(&format!("No bookmark under '{}'", key)),
+                    Err(error) => self.notify(&error),
+                }
+            },
+        }
+    }
+
+    pub fn move_cursor_to(&mut self, name: &str) {
+        if let Some(index) = self.context_ref().current_siblings.iter()
+                .position(|entry| entry.name == name) {
+            self.context_mut().current_index = index;
+            self.update_current_entry_by_index();
+        }
+    }
+
     pub fn go_home(&mut self) {
+        if self.grep_index().is_some() { return self.move_grep_cursor_to(0); }
         if self.inside_empty_dir() { return }
         self.context_mut().current_index = 0;
         self.update_current_entry_by_index();
     }
 
+    // Counting from 1, like the count of `G` does
+    pub fn go_to_line(&mut self, line: usize) {
+        let index = line.saturating_sub(1);
+        if self.grep_index().is_some() { return self.move_grep_cursor_to(index); }
+        if self.inside_empty_dir() { return }
+        self.context_mut().current_index = index.min(self.context_ref().current_siblings.len() - 1);
+        self.update_current_entry_by_index();
+    }
+
     pub fn go_end(&mut self) {
+        if self.grep_index().is_some() { return self.move_grep_cursor_to(usize::MAX); }
         if self.inside_empty_dir() { return }
         self.context_mut().current_index = self.context_ref().current_siblings.len() - 1;
         self.update_current_entry_by_index();
     }
 //-----------------------------------------------------------------------------
-    pub fn draw(&mut self, mut cs: &mut ColorSystem) {
-        self.renderer.clear(&mut cs, self.settings.primary_paint);
+    pub fn draw(&mut self, cs: &mut ColorSystem) {
+        self.renderer.clear(cs, self.settings.primary_paint);
 
         self.update_transfer_progress();
+        self.update_find_results();
+        self.update_grep_results();
         self.update_notification();
 
-        self.renderer.draw_borders(&mut cs, self.settings.primary_paint);
-        self.renderer.draw_left_column(&mut cs, &self.context_ref().parent_siblings,
+        self.renderer.draw_borders(cs, self.settings.primary_paint);
+        self.renderer.draw_left_column(cs, &self.context_ref().parent_siblings,
             self.context_ref().parent_index, self.context_ref().parent_siblings_shift);
-        self.renderer.draw_middle_column(&mut cs, self.inside_empty_dir(),
-            &self.context_ref().current_siblings,
-            self.context_ref().current_index,
-            self.context_ref().current_siblings_shift);
-        self.renderer.draw_right_column(&mut cs, &self.context_ref().right_column,
+        if let Some(InputMode::PatternRename(PatternRenameTools {pattern, ..})) =
+                self.context_ref().input_mode.as_ref() {
+            self.draw_rename_preview(cs, pattern);
+        } else if let Some(InputMode::Grep(GrepTools {cursor_index, matches, index, shift, searching, ..})) =
+                self.context_ref().input_mode.as_ref() {
+            let mut rows: Vec<String> = matches.iter().map(grep_line_of).collect();
+            if rows.is_empty() && cursor_index.is_none() && !searching { rows.push("No matches".to_string()); }
+            self.renderer.draw_rows_with_cursor(cs, &rows, *index, *shift,
+                self.settings.primary_paint, self.grep_index().is_some());
+        } else {
+            self.renderer.draw_middle_column(cs, self.inside_empty_dir(),
+                &self.context_ref().current_siblings,
+                self.context_ref().current_index,
+                self.context_ref().current_siblings_shift,
+                self.visual_range());
+        }
+        self.renderer.draw_right_column(cs, &self.context_ref().right_column,
             self.settings.preview_paint);
 
         let mut bottom_bar = Bar::with_y_and_width(
             self.renderer.display_settings.height - 1, self.renderer.display_settings.width);
-        self.renderer.maybe_draw_input_mode(&mut cs, &mut bottom_bar, &self.context_ref().input_mode);
-        self.renderer.draw_current_permission(&mut cs, &mut bottom_bar,
-            &self.context_ref().current_permissions);
-        self.renderer.draw_current_size(&mut cs, &mut bottom_bar,
-            self.current_entry_ref().map(|e| e.size));
-        self.renderer.maybe_draw_additional_info_for_current(&mut cs, &mut bottom_bar,
+        self.renderer.maybe_draw_input_mode(cs, &mut bottom_bar, &self.context_ref().input_mode);
+        self.renderer.maybe_draw_visual_range(cs, &mut bottom_bar, self.visual_range());
+        self.renderer.maybe_draw_pending_keys(cs, &mut bottom_bar, &self.pending_keys);
+        self.renderer.draw_status_fields(cs, &mut bottom_bar,
+            &self.context_ref().current_details, &self.settings.status_fields);
+        self.renderer.maybe_draw_additional_info_for_current(cs, &mut bottom_bar,
             &self.context_ref().additional_entry_info);
-        self.renderer.draw_current_dir_siblings_count(&mut cs, &mut bottom_bar,
+        self.renderer.draw_current_dir_siblings_count(cs, &mut bottom_bar,
             &self.context_ref().current_siblings);
-        self.renderer.draw_cumulative_size_text(&mut cs, &mut bottom_bar,
+        self.renderer.draw_sorting(cs, &mut bottom_bar, &self.current_sorting());
+        self.renderer.draw_cumulative_size_text(cs, &mut bottom_bar,
             &self.context_ref().cumulative_size_text);
-        self.renderer.draw_notification(&mut cs, &mut bottom_bar, &self.notification);
-        self.renderer.maybe_draw_selection_warning(&mut cs, &mut bottom_bar, self.selected.is_empty());
+        self.renderer.maybe_draw_transfers_summary(cs, &mut bottom_bar, &self.transfers);
+        self.renderer.draw_notification(cs, &mut bottom_bar, &self.notification);
+        self.renderer.maybe_draw_selection_warning(cs, &mut bottom_bar, self.selected.is_empty());
 
         let mut top_bar = Bar::with_y_and_width(0, self.renderer.display_settings.width);
-        self.renderer.draw_current_path(&mut cs, &mut top_bar, self.inside_empty_dir(),
+        self.renderer.draw_current_path(cs, &mut top_bar, self.inside_empty_dir(),
             &self.context_ref().parent_path, &self.context_ref().current_path);
-        self.renderer.draw_tabs(&mut cs, &mut top_bar, &self.tabs, self.current_tab_index);
+        self.renderer.draw_tabs(cs, &mut top_bar, &self.tabs, self.current_tab_index);
+        self.renderer.maybe_draw_filter(cs, &mut top_bar, &self.current_tab_ref().filter);
+
+        if self.show_transfers { self.draw_transfers_popup(cs); }
+        if self.show_details { self.draw_details_popup(cs); }
+        if self.resolving_conflict() { self.draw_conflict_popup(cs); }
+        if let Some(InputMode::Confirm(ConfirmTools { action: ConfirmedAction::BulkRename(renames), .. }))
+                = self.context_ref().input_mode.as_ref() {
+            self.draw_renames_popup(cs, renames);
+        }
+        if let Some(InputMode::Permissions(editor)) = self.context_ref().input_mode.as_ref() {
+            self.renderer.draw_list_popup(cs, &editor.title(), &editor.rows());
+        }
 
         self.renderer.maybe_draw_input_mode_cursor(&self.context_ref().input_mode);
 
         self.renderer.refresh();
     }
 
+    pub fn set_pending_keys(&mut self, text: String) {
+        self.pending_keys = text;
+    }
+
     pub fn draw_available_matches(&self, cs: &mut ColorSystem,
             matches: &Vec<Match>, completion_count: usize) {
         self.renderer.draw_available_matches(cs, matches, completion_count);
     }
+
+    fn draw_conflict_popup(&self, cs: &mut ColorSystem) {
+        let pending = self.pending_paste.as_ref().unwrap();
+        let item = &pending.conflicts[0];
+        let both_dirs = is_dir(&item.src) && is_dir(&item.dst);
+        let mut title = format!("{} already exists", path_to_str(&item.dst));
+        if pending.conflicts.len() > 1 {
+            title += &format!(" ({} more conflicts)", pending.conflicts.len() - 1);
+        }
+        let newer = if is_newer(&item.src, &item.dst) { "newer" } else { "not newer" };
+        let mut rows = vec![
+            ("o".to_string(), "Overwrite the existing one".to_string()),
+            ("s".to_string(), "Skip".to_string()),
+            ("r".to_string(), format!("Rename to {}", file_name(&free_path_like(&item.dst)))),
+            ("n".to_string(), format!("Keep the newer one (the pasted one is {})", newer)),
+        ];
+        if both_dirs { rows.push(("m".to_string(), "Merge the directories".to_string())); }
+        rows.push(("O S R N M".to_string(), "Do the same for all the remaining conflicts".to_string()));
+        rows.push(("Esc".to_string(), "Cancel the paste".to_string()));
+        self.renderer.draw_list_popup(cs, &title, &rows);
+    }
+
+    fn draw_renames_popup(&self, cs: &mut ColorSystem, renames: &[Rename]) {
+        let parent_path = &self.context_ref().parent_path;
+        let rows: Vec<(String, String)> = renames.iter().map(|(from, to)| ("".to_string(),
+                format!("{}  ->  {}", line_of(from, parent_path), line_of(to, parent_path))))
+            .collect();
+        self.renderer.draw_list_popup(cs, "Planned renames", &rows);
+    }
+
+    // Replaces the middle column while the pattern is being typed
+    fn draw_rename_preview(&self, cs: &mut ColorSystem, pattern: &str) {
+        let targets = self.pattern_rename_targets();
+        let mut rows = Vec::new();
+        match self.planned_pattern_renames(pattern) {
+            Ok(renames) => {
+                let collided = collisions(&renames);
+                rows.push((format!("{} of {} entries change", renames.len(), targets.len()),
+                           self.settings.primary_paint));
+                let parent_path = &self.context_ref().parent_path;
+                for path in targets.iter() {
+                    let row = match renames.iter().position(|(from, _)| from == path) {
+                        Some(index) => (format!("{}  ->  {}", line_of(path, parent_path),
+                                                line_of(&renames[index].1, parent_path)),
+                                        if collided[index] { Paint::with_fg_bg(Color::Red, Color::Default) }
+                                        else               { Paint::with_fg_bg(Color::Green, Color::Default) }),
+                        None => (line_of(path, parent_path), self.settings.primary_paint),
+                    };
+                    rows.push(row);
+                }
+            },
+            Err(error) => {
+                rows.push((error, Paint::with_fg_bg(Color::Red, Color::Default)));
+                let parent_path = &self.context_ref().parent_path;
+                rows.extend(targets.iter()
+                    .map(|path| (line_of(path, parent_path), self.settings.primary_paint)));
+            },
+        }
+        self.renderer.draw_rename_preview(cs, &rows);
+    }
+
+    fn draw_details_popup(&self, cs: &mut ColorSystem) {
+        match self.context_ref().current_details.as_ref() {
+            Some(details) => self.renderer.draw_list_popup(cs, "Details", &details_rows(details)),
+            None => self.renderer.draw_list_popup(cs, "Details",
+                                                  &[("".to_string(), "Nothing here".to_string())]),
+        }
+    }
+
+    fn draw_transfers_popup(&self, cs: &mut ColorSystem) {
+        let mut rows: Vec<(String, String)> = self.transfers.iter().enumerate()
+            .map(|(index, transfer)| ((index + 1).to_string(), transfer.details()))
+            .collect();
+        if rows.is_empty() { rows.push(("".to_string(), "No transfers in progress".to_string())); }
+        self.renderer.draw_list_popup(cs, "Transfers", &rows);
+    }
+
+    pub fn draw_bookmarks(&self, cs: &mut ColorSystem, action: BookmarkAction) {
+        let mut rows: Vec<(String, String)> = self.bookmarks.entries().iter()
+            .map(|(key, path)| (key.to_string(), path.clone()))
+            .collect();
+        if rows.is_empty() { rows.push(("".to_string(), "No bookmarks yet".to_string())); }
+        self.renderer.draw_list_popup(cs, prompt_of(action), &rows);
+    }
 //-----------------------------------------------------------------------------
     pub fn select_under_cursor(&mut self) {
         if let Some(path) = self.context_ref().current_path.as_ref() {
@@ -889,6 +1997,23 @@ impl System {
         }
     }
 
+    // Toggles every entry once, even when the range reaches the last one
+    pub fn toggle_following(&mut self, count: usize) {
+        if count <= 1 || self.inside_empty_dir() || self.doing_grep() { return self.select_under_cursor(); }
+        let parent_path = self.context_ref().parent_path.clone();
+        let paths: Vec<PathBuf> = self.context_ref().current_siblings.iter()
+            .skip(self.context_ref().current_index).take(count)
+            .map(|entry| parent_path.join(&entry.name)).collect();
+        for path in paths.iter() {
+            match self.selected.iter().position(|item| item == path) {
+                Some(index) => { self.selected.remove(index); },
+                None        => self.selected.push(path.clone()),
+            }
+        }
+        self.sync_selection_flags();
+        self.down_by(paths.len());
+    }
+
     pub fn clear_selection(&mut self) {
         self.selected.clear();
         self.potential_transfer_data = None;
@@ -903,6 +2028,87 @@ impl System {
         self.maybe_sync_search_backup_selection_for_current_siblings();
     }
 
+    // Only the cursor moves until the range is confirmed or cancelled
+    pub fn start_visual(&mut self) {
+        if self.inside_empty_dir() || self.doing_grep() { return; }
+        self.visual_anchor = Some(self.context_ref().current_index);
+    }
+
+    pub fn in_visual_mode(&self) -> bool {
+        self.visual_anchor.is_some()
+    }
+
+    // The first and the last index of the range, which the siblings may have shrunk
+    fn visual_range(&self) -> Option<(usize, usize)> {
+        let anchor = self.visual_anchor?;
+        if self.inside_empty_dir() { return None; }
+        let last_index = self.context_ref().current_siblings.len() - 1;
+        let (anchor, cursor) = (anchor.min(last_index), self.context_ref().current_index);
+        Some((anchor.min(cursor), anchor.max(cursor)))
+    }
+
+    pub fn cancel_visual(&mut self) {
+        self.visual_anchor = None;
+    }
+
+    // Adds the range to the selected entries, or removes it from them
+    pub fn finish_visual(&mut self, select: bool) {
+        let range = self.visual_range();
+        self.visual_anchor = None;
+        let (first, last) = match range {
+            Some(range) => range,
+            None        => return,
+        };
+        let parent_path = self.context_ref().parent_path.clone();
+        let paths: Vec<PathBuf> = self.context_ref().current_siblings[first..=last].iter()
+            .map(|entry| parent_path.join(&entry.name)).collect();
+        self.select_paths(paths, select);
+    }
+
+    // Goes through the entries of the current directory, those on screen
+    fn select_matching(&mut self, criteria: &[Criterion], select: bool) {
+        let parent_path = self.context_ref().parent_path.clone();
+        let matching: Vec<PathBuf> = self.context_ref().current_siblings.iter()
+            .map(|entry| (entry, parent_path.join(&entry.name)))
+            .filter(|(entry, path)| meets_all(criteria, entry, path))
+            .map(|(_, path)| path)
+            .collect();
+        self.select_paths(matching, select);
+    }
+
+    fn select_paths(&mut self, paths: Vec<PathBuf>, select: bool) {
+        let mut changed = 0;
+        for path in paths.into_iter() {
+            let index = self.selected.iter().position(|item| *item == path);
+            match (select, index) {
+                (true, None)         => { self.selected.push(path); changed += 1; },
+                (false, Some(index)) => { self.selected.remove(index); changed += 1; },
+                _ => {},
+            }
+        }
+        self.sync_selection_flags();
+        let verb = if select { "Selected" } else { "Deselected" };
+        self.notify(&format!("{} {} {}", verb, changed, if changed == 1 { "entry" } else { "entries" }));
+    }
+
+    // Makes the marks in all the columns agree with the list of selected paths
+    fn sync_selection_flags(&mut self) {
+        let selected: HashSet<PathBuf> = self.selected.iter().cloned().collect();
+        let parent_path = self.context_ref().parent_path.clone();
+        let grandparent = maybe_parent(&parent_path);
+        let current_path = self.context_ref().current_path.clone();
+        if let Some(grandparent) = grandparent {
+            self.context_mut().parent_siblings.iter_mut()
+                .for_each(|e| e.is_selected = selected.contains(&grandparent.join(&e.name)));
+        }
+        self.context_mut().current_siblings.iter_mut()
+            .for_each(|e| e.is_selected = selected.contains(&parent_path.join(&e.name)));
+        if let (Some(siblings), Some(path)) = (self.context_mut().right_column.siblings_mut(), current_path) {
+            siblings.iter_mut().for_each(|e| e.is_selected = selected.contains(&path.join(&e.name)));
+        }
+        self.maybe_sync_search_backup_selection_for_current_siblings();
+    }
+
     pub fn invert_selection(&mut self) {
         let parent_path = self.context_ref().parent_path.clone();
 
@@ -1005,6 +2211,7 @@ impl Drop for System {
 
 //-----------------------------------------------------------------------------
 enum DrawingDelay {
+    Finding,
     Transfering,
     Regular,
 }
@@ -1012,6 +2219,7 @@ enum DrawingDelay {
 impl DrawingDelay {
     fn ms(&self) -> i32 {
         match self {
+            DrawingDelay::Finding => 300,
             DrawingDelay::Transfering => 1000,
             DrawingDelay::Regular => 5000,
         }
@@ -1019,49 +2227,10 @@ impl DrawingDelay {
 }
 //-----------------------------------------------------------------------------
 //-----------------------------------------------------------------------------
-enum TransferType {
-    Yank,
-    Cut,
-}
-
-struct PotentialTransfer {
-    src_paths: Vec<PathBuf>,
-    src_sizes: Vec<Size>,
-    transfer_type: TransferType,
-}
-
-struct Transfer {
-    src_sizes: Vec<Size>,
-    dst_paths: Vec<PathBuf>,
-    dst_sizes: Vec<Option<Size>>,
-    transfer_type: TransferType,
-}
-
-impl PotentialTransfer {
-    fn cut(src_paths: Vec<PathBuf>) -> PotentialTransfer {
-        PotentialTransfer::new(src_paths, TransferType::Cut)
-    }
-
-    fn yank(src_paths: Vec<PathBuf>) -> PotentialTransfer {
-        PotentialTransfer::new(src_paths, TransferType::Yank)
-    }
-
-    fn new(src_paths: Vec<PathBuf>, transfer_type: TransferType) -> PotentialTransfer {
-        let src_sizes: Vec<Size> = src_paths.iter().map(|path| cumulative_size(&path)).collect();
-        PotentialTransfer {
-            src_paths,
-            src_sizes,
-            transfer_type,
-        }
-    }
-
-    fn with_dst_paths(self, dst_paths: Vec<PathBuf>) -> Transfer {
-        let amount = self.src_sizes.len();
-        Transfer {
-            src_sizes: self.src_sizes,
-            dst_sizes: vec![None; amount],
-            dst_paths,
-            transfer_type: self.transfer_type,
-        }
-    }
+// A paste that waits for the user to decide about the conflicts
+struct PendingPaste {
+    potential: PotentialTransfer,
+    resolved: Vec<TransferItem>,
+    conflicts: Vec<TransferItem>, // the first one is being asked about
 }
+//-----------------------------------------------------------------------------
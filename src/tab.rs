@@ -1,4 +1,6 @@
 use crate::context::*;
+use crate::direntry::Sorting;
+use crate::matching::NameFilter;
 use std::path::PathBuf;
 use crate::filesystem::*;
 
@@ -6,6 +8,8 @@ use crate::filesystem::*;
 pub struct Tab {
     pub name: String,
     pub context: Context,
+    pub sorting: Sorting, // unless remembered for the directory
+    pub filter: Option<NameFilter>, // kept while moving around
 }
 
 pub fn tab_name_from_path(path: &PathBuf) -> String {
@@ -0,0 +1,530 @@
+use crate::filesystem::*;
+use crate::notification::*;
+use crate::utils::*;
+
+use std::path::{Path, PathBuf};
+use std::fs::{self, File, FileTimes, Metadata, OpenOptions};
+use std::io::{self, Read, Write};
+use std::time::SystemTime;
+use std::os::unix::fs::symlink;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum TransferType {
+    Yank,
+    Cut,
+}
+
+pub struct PotentialTransfer {
+    pub src_paths: Vec<PathBuf>,
+    pub src_sizes: Vec<Size>,
+    pub transfer_type: TransferType,
+}
+
+// What to do when the destination already exists
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum ConflictPolicy {
+    Ask,
+    Overwrite,
+    Skip,
+    Rename,
+    KeepNewer,
+    Merge, // for directories, the rest get overwritten
+}
+
+pub struct TransferItem {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    pub size: Size,
+    pub replace: bool, // remove the existing dst first, otherwise directories get merged
+}
+
+pub struct Transfer {
+    pub dst_paths: Vec<PathBuf>,
+    pub transfer_type: TransferType,
+
+    pub bytes_total: Size,
+    pub bytes_done: Size,
+    pub current_file: Option<PathBuf>,
+    start_time: SystemTime,
+
+    pub errors: Vec<String>,
+    pub placed: Vec<(PathBuf, PathBuf)>, // fully transferred items that took a free place
+    pub finished: bool,
+    events: Receiver<TransferEvent>,
+}
+
+enum TransferEvent {
+    File(PathBuf), // started working on this one
+    Bytes(Size),   // that many more bytes are done
+    Error(String),
+    Placed(PathBuf, PathBuf),
+    Finished,
+}
+
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+impl PotentialTransfer {
+    pub fn cut(src_paths: Vec<PathBuf>) -> PotentialTransfer {
+        PotentialTransfer::new(src_paths, TransferType::Cut)
+    }
+
+    pub fn yank(src_paths: Vec<PathBuf>) -> PotentialTransfer {
+        PotentialTransfer::new(src_paths, TransferType::Yank)
+    }
+
+    fn new(src_paths: Vec<PathBuf>, transfer_type: TransferType) -> PotentialTransfer {
+        let src_sizes: Vec<Size> = src_paths.iter().map(cumulative_size).collect();
+        PotentialTransfer {
+            src_paths,
+            src_sizes,
+            transfer_type,
+        }
+    }
+
+    // Pairs every source with the place of the same name inside dst_dir
+    pub fn items_into(&self, dst_dir: &Path) -> Vec<TransferItem> {
+        self.src_paths.iter().zip(self.src_sizes.iter())
+            .map(|(src, &size)| TransferItem {
+                src: src.clone(),
+                dst: dst_dir.join(file_name(src)),
+                size,
+                replace: false,
+            }).collect()
+    }
+
+    // Starts the transfer on a worker thread
+    pub fn start(self, items: Vec<TransferItem>) -> Transfer {
+        let (sender, events) = mpsc::channel();
+        let dst_paths = items.iter().map(|item| item.dst.clone()).collect();
+        let bytes_total = items.iter().map(|item| item.size).sum();
+        let transfer_type = self.transfer_type;
+        thread::spawn(move || {
+            for item in items.iter() {
+                transfer_item(item, transfer_type, &sender);
+            }
+            let _ = sender.send(TransferEvent::Finished); // the UI may have gone already
+        });
+
+        Transfer {
+            dst_paths,
+            transfer_type: self.transfer_type,
+            bytes_total,
+            bytes_done: 0,
+            current_file: None,
+            start_time: SystemTime::now(),
+            errors: Vec::new(),
+            placed: Vec::new(),
+            finished: false,
+            events,
+        }
+    }
+}
+
+impl Transfer {
+    // Collects whatever the worker has reported since the last call
+    pub fn poll(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(TransferEvent::File(path))   => self.current_file = Some(path),
+                Ok(TransferEvent::Bytes(bytes)) => self.bytes_done += bytes,
+                Ok(TransferEvent::Error(error)) => self.errors.push(error),
+                Ok(TransferEvent::Placed(src, dst)) => self.placed.push((src, dst)),
+                Ok(TransferEvent::Finished)     => self.finished = true,
+                Err(TryRecvError::Empty)        => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.finished { // the worker died before it was done
+                        self.errors.push("The transfer stopped unexpectedly".to_string());
+                        self.finished = true;
+                    }
+                    break;
+                },
+            }
+        }
+    }
+
+    pub fn percentage(&self) -> u32 {
+        if self.bytes_total == 0 { return 100; }
+        (100 * self.bytes_done / self.bytes_total).min(100) as u32
+    }
+
+    // Bytes per second since the start
+    pub fn throughput(&self) -> Option<Size> {
+        let millis = millis_since(self.start_time);
+        if millis == 0 { return None; }
+        Some((self.bytes_done as u128 * 1000 / millis) as Size)
+    }
+
+    pub fn seconds_left(&self) -> Option<u64> {
+        let throughput = self.throughput()?;
+        if throughput == 0 { return None; }
+        Some(self.bytes_total.saturating_sub(self.bytes_done) / throughput)
+    }
+
+    pub fn verb(&self) -> &'static str {
+        match self.transfer_type {
+            TransferType::Cut  => "Moving",
+            TransferType::Yank => "Copying",
+        }
+    }
+
+    // E.g. "Copying 45% 12.3 M/s 1m05s left"
+    pub fn summary(&self) -> String {
+        let mut text = format!("{} {}%", self.verb(), self.percentage());
+        if let Some(throughput) = self.throughput() {
+            text += &format!(" {}/s", human_size(throughput));
+        }
+        if let Some(seconds) = self.seconds_left() {
+            text += &format!(" {} left", human_duration(seconds));
+        }
+        text
+    }
+
+    // E.g. "Copying 3 entries into /dst: 1.2 G of 2.6 G, file.iso"
+    pub fn details(&self) -> String {
+        let amount = self.dst_paths.len();
+        let into = self.dst_paths.first()
+            .and_then(|path| path.parent())
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        let mut text = format!("{} {} {} into {}: {} of {}",
+            self.summary(), amount, if amount == 1 { "entry" } else { "entries" }, into,
+            human_size(self.bytes_done), human_size(self.bytes_total));
+        if let Some(file) = self.current_file.as_ref() {
+            text += &format!(", {}", file.display());
+        }
+        text
+    }
+}
+
+pub fn transfers_summary(transfers: &[Transfer]) -> Option<String> {
+    match transfers.len() {
+        0 => None,
+        1 => Some(transfers[0].summary()),
+        amount => {
+            let total: Size = transfers.iter().map(|t| t.bytes_total).sum();
+            let done:  Size = transfers.iter().map(|t| t.bytes_done ).sum();
+            let percentage = (100 * done).checked_div(total).map_or(100, |percentage| percentage.min(100));
+            Some(format!("{} transfers {}%", amount, percentage))
+        },
+    }
+}
+//-----------------------------------------------------------------------------
+// Returns None if the item is to be skipped. Never asks, so Ask means Skip here.
+pub fn resolve_conflict(mut item: TransferItem, policy: ConflictPolicy,
+                        transfer_type: TransferType) -> Option<TransferItem> {
+    if item.src == item.dst { // pasting right where it already is
+        return match (transfer_type, policy) {
+            (TransferType::Yank, ConflictPolicy::Skip) => None,
+            (TransferType::Yank, ConflictPolicy::Ask)  => None,
+            (TransferType::Yank, _) => { item.dst = free_path_like(&item.dst); Some(item) },
+            (TransferType::Cut,  _) => None, // already there
+        };
+    }
+    match policy {
+        ConflictPolicy::Ask | ConflictPolicy::Skip => None,
+        ConflictPolicy::Overwrite => { item.replace = true; Some(item) },
+        ConflictPolicy::Rename    => { item.dst = free_path_like(&item.dst); Some(item) },
+        ConflictPolicy::KeepNewer => {
+            if is_newer(&item.src, &item.dst) { item.replace = true; Some(item) }
+            else { None }
+        },
+        ConflictPolicy::Merge => {
+            item.replace = !(is_dir(&item.src) && is_dir(&item.dst));
+            Some(item)
+        },
+    }
+}
+
+pub fn exists(path: &Path) -> bool {
+    path.symlink_metadata().is_ok() // a broken symlink exists too
+}
+
+pub fn is_newer(path: &PathBuf, than: &PathBuf) -> bool {
+    let modified = |path: &PathBuf| path.symlink_metadata().and_then(|meta| meta.modified()).ok();
+    modified(path) > modified(than)
+}
+
+// The first of `name (1).ext`, `name (2).ext`, ... that does not exist yet.
+// Directories and dot-files without an extension get the number at the end.
+pub fn free_path_like(path: &PathBuf) -> PathBuf {
+    let name = file_name(path);
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if index > 0 && !is_dir(path) => name.split_at(index),
+        _ => (name.as_str(), ""),
+    };
+    let stem = strip_copy_number(stem);
+    let dir = path.parent().unwrap();
+    (1..).map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+         .find(|candidate| !exists(candidate))
+         .unwrap()
+}
+
+// "name (3)" -> "name", so that copies of copies do not pile up the numbers
+fn strip_copy_number(stem: &str) -> &str {
+    if stem.ends_with(')') {
+        if let Some(open) = stem.rfind(" (") {
+            let number = &stem[open + 2 .. stem.len() - 1];
+            if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+                return &stem[..open];
+            }
+        }
+    }
+    stem
+}
+//-----------------------------------------------------------------------------
+fn transfer_item(item: &TransferItem, transfer_type: TransferType, sender: &Sender<TransferEvent>) {
+    let (src, dst) = (&item.src, &item.dst);
+    if dst.starts_with(src) {
+        let error = io::Error::other("cannot transfer a directory into itself");
+        report(sender, src, error);
+        return;
+    }
+    if item.replace && exists(dst) {
+        if src.starts_with(dst) {
+            let error = io::Error::other("cannot replace a directory containing the source");
+            report(sender, dst, error);
+            return;
+        }
+        let result = if is_dir(dst) { fs::remove_dir_all(dst) } else { fs::remove_file(dst) };
+        if let Err(e) = result { report(sender, dst, e); return; }
+    }
+    let merging = exists(dst); // can not be simply undone then
+    let done = match transfer_type {
+        TransferType::Yank => copy_entry(src, dst, sender),
+        TransferType::Cut  => move_entry(src, dst, item.size, sender),
+    };
+    if done && !merging {
+        let _ = sender.send(TransferEvent::Placed(src.clone(), dst.clone()));
+    }
+}
+
+// Moves right away, falling back to copying between filesystems
+pub fn move_now(src: &PathBuf, dst: &PathBuf) -> Result<(), String> {
+    let (sender, events) = mpsc::channel();
+    move_entry(src, dst, cumulative_size(src), &sender);
+    drop(sender);
+    let errors: Vec<String> = events.iter().filter_map(|event| match event {
+        TransferEvent::Error(error) => Some(error),
+        _ => None,
+    }).collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors.join("; ")) }
+}
+
+fn report(sender: &Sender<TransferEvent>, path: &Path, error: io::Error) {
+    let _ = sender.send(TransferEvent::Error(format!("{}: {}", path.display(), error)));
+}
+
+// Returns whether everything was copied without errors
+fn copy_entry(src: &PathBuf, dst: &PathBuf, sender: &Sender<TransferEvent>) -> bool {
+    let meta = match src.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(e)   => { report(sender, src, e); return false; },
+    };
+    let file_type = meta.file_type();
+    if !file_type.is_symlink() && !file_type.is_dir() && !file_type.is_file() {
+        report(sender, src, io::Error::other("cannot copy a special file"));
+        return false;
+    }
+    if let Err(e) = clear_place_for(dst, file_type.is_dir()) { report(sender, dst, e); return false; }
+    if file_type.is_symlink() {
+        let result = fs::read_link(src).and_then(|target| symlink(target, dst));
+        if let Err(e) = result { report(sender, dst, e); return false; }
+        let _ = sender.send(TransferEvent::Bytes(meta.len()));
+        true
+    } else if file_type.is_dir() {
+        copy_dir(src, dst, &meta, sender)
+    } else {
+        let _ = sender.send(TransferEvent::File(src.clone()));
+        if let Err(e) = copy_file(src, dst, &meta, sender) { report(sender, dst, e); return false; }
+        true
+    }
+}
+
+// When merging, whatever is already in place of an entry gets removed so that
+// nothing is written through a symlink that is there. Only a real directory
+// stays, to merge a directory into.
+fn clear_place_for(dst: &PathBuf, is_dir: bool) -> io::Result<()> {
+    let meta = match dst.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if meta.is_dir() {
+        if is_dir { return Ok(()); }
+        return Err(io::Error::other("a directory is in the way"));
+    }
+    if is_dir && dst.is_dir() { // follows the symlink
+        return Err(io::Error::other("will not merge into a symlinked directory"));
+    }
+    fs::remove_file(dst)
+}
+
+fn copy_dir(src: &PathBuf, dst: &PathBuf, meta: &Metadata, sender: &Sender<TransferEvent>) -> bool {
+    if let Err(e) = fs::create_dir(dst) {
+        if e.kind() != io::ErrorKind::AlreadyExists { report(sender, dst, e); return false; }
+    }
+    let entries = match fs::read_dir(src) {
+        Ok(entries) => entries,
+        Err(e)      => { report(sender, src, e); return false; },
+    };
+    let mut all_ok = true;
+    for entry in entries {
+        match entry {
+            Ok(entry) => all_ok &= copy_entry(&entry.path(), &dst.join(entry.file_name()), sender),
+            Err(e)    => { report(sender, src, e); all_ok = false; },
+        }
+    }
+    // Only after the contents, so that they neither change the time
+    // nor get rejected by a read-only directory
+    let result = set_times(dst, meta).and_then(|_| fs::set_permissions(dst, meta.permissions()));
+    if let Err(e) = result { report(sender, dst, e); return false; }
+    all_ok
+}
+
+// Reports the progress after every chunk
+fn copy_file(src: &PathBuf, dst: &PathBuf, meta: &Metadata,
+             sender: &Sender<TransferEvent>) -> io::Result<()> {
+    let mut reader = File::open(src)?;
+    let mut writer = OpenOptions::new().write(true).create_new(true).open(dst)?; // never through a symlink
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        let _ = sender.send(TransferEvent::Bytes(read as Size));
+    }
+    writer.set_permissions(meta.permissions())?;
+    writer.set_times(file_times_of(meta)?)
+}
+
+fn file_times_of(meta: &Metadata) -> io::Result<FileTimes> {
+    Ok(FileTimes::new()
+        .set_accessed(meta.accessed()?)
+        .set_modified(meta.modified()?))
+}
+
+fn set_times(path: &PathBuf, meta: &Metadata) -> io::Result<()> {
+    File::open(path)?.set_times(file_times_of(meta)?)
+}
+
+// Returns whether everything was moved without errors
+fn move_entry(src: &PathBuf, dst: &PathBuf, size: Size, sender: &Sender<TransferEvent>) -> bool {
+    if is_dir(src) && is_dir(dst) { // merging
+        return move_dir_contents(src, dst, sender);
+    }
+    let _ = sender.send(TransferEvent::File(src.clone()));
+    match fs::rename(src, dst) {
+        Ok(()) => { let _ = sender.send(TransferEvent::Bytes(size)); true },
+        Err(ref e) if e.raw_os_error() == Some(libc::EXDEV) => { // src and dst are on different filesystems
+            // Only remove the source if it has been fully copied
+            if !copy_entry(src, dst, sender) { return false; }
+            let result = if is_dir(src) { fs::remove_dir_all(src) } else { fs::remove_file(src) };
+            if let Err(e) = result { report(sender, src, e); return false; }
+            true
+        },
+        Err(e) => { report(sender, src, e); false },
+    }
+}
+
+fn move_dir_contents(src: &PathBuf, dst: &Path, sender: &Sender<TransferEvent>) -> bool {
+    let entries = match fs::read_dir(src) {
+        Ok(entries) => entries,
+        Err(e)      => { report(sender, src, e); return false; },
+    };
+    let mut success = true;
+    for entry in entries {
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+                success &= move_entry(&path, &dst.join(entry.file_name()), cumulative_size(&path), sender);
+            },
+            Err(e) => { report(sender, src, e); success = false; },
+        }
+    }
+    // Fails if something was left
+    if let Err(e) = fs::remove_dir(src) { report(sender, src, e); return false; }
+    success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    // A fresh directory for one test, the tests run in parallel
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vffm-transfer-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Returns whether it went fine, with the errors
+    fn copy(src: &PathBuf, dst: &PathBuf) -> (bool, Vec<String>) {
+        let (sender, events) = mpsc::channel();
+        let done = copy_entry(src, dst, &sender);
+        drop(sender);
+        let errors = events.iter().filter_map(|event| match event {
+            TransferEvent::Error(error) => Some(error),
+            _ => None,
+        }).collect();
+        (done, errors)
+    }
+
+    #[test]
+    fn merging_replaces_a_planted_symlink_instead_of_writing_through_it() {
+        let dir = test_dir("planted-file");
+        fs::create_dir_all(dir.join("src/d")).unwrap();
+        fs::write(dir.join("src/d/file"), "new").unwrap();
+        fs::create_dir_all(dir.join("dst/d")).unwrap();
+        fs::write(dir.join("victim"), "old").unwrap();
+        symlink(dir.join("victim"), dir.join("dst/d/file")).unwrap();
+
+        assert_eq!(copy(&dir.join("src/d"), &dir.join("dst/d")), (true, Vec::new()));
+        assert_eq!(fs::read_to_string(dir.join("victim")).unwrap(), "old");
+        assert!(!fs::symlink_metadata(dir.join("dst/d/file")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(dir.join("dst/d/file")).unwrap(), "new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merging_refuses_a_symlinked_directory() {
+        let dir = test_dir("planted-dir");
+        fs::create_dir_all(dir.join("src/d/sub")).unwrap();
+        fs::write(dir.join("src/d/sub/file"), "new").unwrap();
+        fs::create_dir_all(dir.join("dst/d")).unwrap();
+        fs::create_dir(dir.join("outside")).unwrap();
+        fs::set_permissions(dir.join("outside"), fs::Permissions::from_mode(0o700)).unwrap();
+        symlink(dir.join("outside"), dir.join("dst/d/sub")).unwrap();
+
+        let (done, errors) = copy(&dir.join("src/d"), &dir.join("dst/d"));
+        assert!(!done);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with("will not merge into a symlinked directory"));
+        assert!(!dir.join("outside/file").exists());
+        assert_eq!(fs::metadata(dir.join("outside")).unwrap().permissions().mode() & 0o777, 0o700);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merging_keeps_what_is_only_in_the_destination() {
+        let dir = test_dir("merge");
+        fs::create_dir_all(dir.join("src/d")).unwrap();
+        fs::write(dir.join("src/d/both"), "new").unwrap();
+        fs::create_dir_all(dir.join("dst/d")).unwrap();
+        fs::write(dir.join("dst/d/both"), "old").unwrap();
+        fs::write(dir.join("dst/d/only"), "kept").unwrap();
+
+        assert_eq!(copy(&dir.join("src/d"), &dir.join("dst/d")), (true, Vec::new()));
+        assert_eq!(fs::read_to_string(dir.join("dst/d/both")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("dst/d/only")).unwrap(), "kept");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
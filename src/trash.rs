@@ -0,0 +1,136 @@
+use crate::config::*;
+use crate::utils::*;
+
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::DirBuilderExt;
+
+
+// The home trash of the freedesktop.org specification: a trashed entry lives
+// in `files/<name>` and its origin is recorded in `info/<name>.trashinfo`
+//
+//     [Trash Info]
+//     Path=/home/user/some%20file.txt
+//     DeletionDate=2020-06-14T21:03:45
+//-----------------------------------------------------------------------------
+pub struct TrashInfo {
+    pub original_path: PathBuf,
+    pub deletion_date: String,
+}
+
+pub fn home_trash_dir() -> Option<PathBuf> {
+    // Next to our own data dir, i.e. $XDG_DATA_HOME/Trash
+    data_dir().and_then(|dir| dir.parent().map(|parent| parent.join("Trash")))
+}
+
+pub fn trash_files_dir() -> Option<PathBuf> {
+    home_trash_dir().map(|dir| dir.join("files"))
+}
+
+pub fn is_trashed(path: &Path) -> bool {
+    trash_files_dir().is_some_and(|files| path.parent() == Some(files.as_path()))
+}
+
+// Returns where the entry has ended up in the trash
+pub fn move_to_trash(path: &PathBuf) -> Result<PathBuf, String> {
+    let trash = home_trash_dir().ok_or("Could not locate the trash directory")?;
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    for dir in [&files, &info].iter() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+            .map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+    let name = path.file_name().ok_or(format!("Can not trash {}", path.display()))?;
+
+    // Creating the info file atomically is what reserves the name
+    let mut number = 1;
+    let (trashed_name, info_path) = loop {
+        let mut trashed_name = name.to_os_string();
+        if number > 1 { trashed_name.push(format!(".{}", number)); }
+        let mut info_name = trashed_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(info_name);
+        number += 1;
+        if fs::symlink_metadata(files.join(&trashed_name)).is_ok() { continue; }
+        match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(mut file) => {
+                // The specification wants the local time
+                let contents = format!("[Trash Info]\nPath={}\nDeletionDate={}\n",
+                    percent_encode(path.as_os_str().as_bytes()), local_date_time(now_seconds(), 'T'));
+                if let Err(e) = file.write_all(contents.as_bytes()) {
+                    let _ = fs::remove_file(&info_path);
+                    return Err(format!("Could not write {}: {}", info_path.display(), e));
+                }
+                break (trashed_name, info_path);
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Could not write {}: {}", info_path.display(), e)),
+        }
+    };
+
+    let trashed = files.join(trashed_name);
+    fs::rename(path, &trashed).map(|_| trashed).map_err(|e| {
+        let _ = fs::remove_file(&info_path);
+        if e.raw_os_error() == Some(libc::EXDEV) {
+            format!("{} is on another filesystem than the trash, delete it permanently instead",
+                    path.display())
+        } else {
+            format!("Could not trash {}: {}", path.display(), e)
+        }
+    })
+}
+
+pub fn trash_info_of(trashed: &Path) -> Option<TrashInfo> {
+    if !is_trashed(trashed) { return None; }
+    let contents = fs::read_to_string(info_path_of(trashed)?).ok()?;
+    let mut original_path = None;
+    let mut deletion_date = String::new();
+    for line in contents.lines() {
+        if let Some(path) = line.strip_prefix("Path=") {
+            original_path = Some(PathBuf::from(OsString::from_vec(percent_decode(path))));
+        } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+            deletion_date = date.replace('T', " ");
+        }
+    }
+    original_path.map(|original_path| TrashInfo { original_path, deletion_date })
+}
+
+// Returns where the entry has been put back to
+pub fn restore(trashed: &PathBuf) -> Result<PathBuf, String> {
+    let info = trash_info_of(trashed)
+        .ok_or(format!("No trash info for {}", trashed.display()))?;
+    let original = info.original_path;
+    if fs::symlink_metadata(&original).is_ok() {
+        return Err(format!("Can not restore, {} already exists", original.display()));
+    }
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Could not create {}: {}", parent.display(), e))?;
+    }
+    fs::rename(trashed, &original)
+        .map_err(|e| format!("Could not restore {}: {}", original.display(), e))?;
+    if let Some(info_path) = info_path_of(trashed) { let _ = fs::remove_file(info_path); }
+    Ok(original)
+}
+
+// Also forgets the trash info if the entry was in the trash
+pub fn delete_permanently(path: &PathBuf) -> Result<(), String> {
+    let metadata = fs::symlink_metadata(path)
+        .map_err(|e| format!("Could not delete {}: {}", path.display(), e))?;
+    let result = if metadata.is_dir() { fs::remove_dir_all(path) }
+                 else                 { fs::remove_file(path) };
+    result.map_err(|e| format!("Could not delete {}: {}", path.display(), e))?;
+    if is_trashed(path) {
+        if let Some(info_path) = info_path_of(path) { let _ = fs::remove_file(info_path); }
+    }
+    Ok(())
+}
+
+fn info_path_of(trashed: &Path) -> Option<PathBuf> {
+    let mut info_name = trashed.file_name()?.to_os_string();
+    info_name.push(".trashinfo");
+    home_trash_dir().map(|trash| trash.join("info").join(info_name))
+}
@@ -6,20 +6,33 @@ pub fn chars_amount(string: &str) -> usize {
     string.chars().count()
 }
 
+// The input cursors count chars, the String methods want bytes
+pub fn byte_index_of_char(string: &str, char_index: usize) -> usize {
+    string.char_indices().nth(char_index).map_or(string.len(), |(index, _)| index)
+}
+
+const DELIMITER: &str = "...";
+const LEAVE_AT_END: usize = 5;
+
 pub fn truncate_with_delimiter(string: &str, max_length: Coord) -> String {
     let chars_amount = chars_amount(&string);
     if chars_amount > max_length as usize {
-        let delimiter = "...";
-        let leave_at_end = 5;
-        let total_end_len = leave_at_end + delimiter.len();
-        let start = max_length as usize - total_end_len;
-        let end = chars_amount - leave_at_end;
-        replace_range_with(string, start..end, delimiter)
+        let start = kept_before_delimiter(string, max_length);
+        let end = chars_amount - LEAVE_AT_END;
+        replace_range_with(string, start..end, DELIMITER)
     } else {
         string.clone().to_string()
     }
 }
 
+// The amount of leading chars that `truncate_with_delimiter` leaves in place
+pub fn kept_before_delimiter(string: &str, max_length: Coord) -> usize {
+    let chars_amount = chars_amount(string);
+    if chars_amount > max_length as usize {
+        max_length as usize - (LEAVE_AT_END + DELIMITER.len())
+    } else { chars_amount }
+}
+
 pub fn maybe_truncate(string: &str, max_length: usize) -> String {
     let mut result = String::new();
     let string = string.replace("\r", "^M").replace("\t", "    "); // assume tab_size=4
@@ -31,6 +44,84 @@ pub fn maybe_truncate(string: &str, max_length: usize) -> String {
 }
 
 
+// Keeps the path separators, so that e.g. `/a b` becomes `/a%20b`
+pub fn percent_encode(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~'
+                => result.push(byte as char),
+            _   => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+pub fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut result = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                result.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        result.push(bytes[index]);
+        index += 1;
+    }
+    result
+}
+
+// E.g. "1h02m", "3m05s", "12s"
+pub fn human_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds / 60) % 60, seconds % 60);
+    if hours > 0        { format!("{}h{:02}m", hours, minutes) }
+    else if minutes > 0 { format!("{}m{:02}s", minutes, seconds) }
+    else                { format!("{}s", seconds) }
+}
+
+// E.g. "2020-06-14 21:03:45" in the local time zone, the separator goes between the date and the time
+pub fn local_date_time(seconds: i64, separator: char) -> String {
+    let time = seconds as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&time, &mut tm) };
+    format!("{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}", tm.tm_year + 1900, tm.tm_mon + 1,
+            tm.tm_mday, separator, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+pub fn now_seconds() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64).unwrap_or(0)
+}
+
+// E.g. "3h ago", "5d ago", or "in 2m" for the future
+pub fn relative_time(seconds: i64, now: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    let difference = (now - seconds).abs();
+    let amount = if      difference < MINUTE    { format!("{}s",  difference) }
+                 else if difference < HOUR      { format!("{}m",  difference / MINUTE) }
+                 else if difference < DAY       { format!("{}h",  difference / HOUR) }
+                 else if difference < 30 * DAY  { format!("{}d",  difference / DAY) }
+                 else if difference < 365 * DAY { format!("{}mo", difference / (30 * DAY)) }
+                 else                           { format!("{}y",  difference / (365 * DAY)) };
+    if seconds > now { format!("in {}", amount) } else { format!("{} ago", amount) }
+}
+
+// Ignores the case unless the pattern has an uppercase letter
+pub fn contains_pattern(string: &str, pattern: &str) -> bool {
+    if pattern.is_empty() { return true; }
+    let pattern_lowercase = pattern.to_lowercase();
+    let case_sensitive = pattern_lowercase != pattern;
+    if case_sensitive { string               .contains( pattern          ) }
+    else              { string.to_lowercase().contains(&pattern_lowercase) }
+}
+
 // Does not validate the range
 // May implement in the future: https://crates.io/crates/unicode-segmentation
 pub fn replace_range_with<R>(string: &str, chars_range: R, replacement: &str) -> String